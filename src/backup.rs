@@ -0,0 +1,51 @@
+//! Rotating copies of previous versions of the database file.
+//!
+//! Backups are kept next to the database, with the most recent one at `<path>.bak.1`, and the
+//! oldest at `<path>.bak.N`, where N is [COUNT].
+
+use crate::error::Result;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The number of previous versions of the database to keep.
+pub const COUNT: u8 = 5;
+
+/// Returns the path of the nth most recent backup of the database at the given path, starting
+/// from 1.
+pub fn path(db_path: &Path, n: u8) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(format!(".bak.{n}"));
+
+    PathBuf::from(path)
+}
+
+/// Shifts each existing backup to the next oldest slot, dropping the oldest one, and saves the
+/// current contents of the database as the most recent backup. Does nothing if the database
+/// doesn't exist yet.
+pub fn rotate(db_path: &Path) -> Result<()> {
+    if !db_path.exists() {
+        return Ok(());
+    }
+
+    ignore_not_found(fs::remove_file(path(db_path, COUNT)))?;
+    for n in (1..COUNT).rev() {
+        ignore_not_found(fs::rename(path(db_path, n), path(db_path, n + 1)))?;
+    }
+
+    // The database is about to be replaced by a rename, so a hard link keeps the current contents
+    // around without copying them. Not all file systems support hard links though.
+    let latest = path(db_path, 1);
+    if fs::hard_link(db_path, &latest).is_err() {
+        fs::copy(db_path, &latest)?;
+    }
+
+    Ok(())
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
@@ -0,0 +1,98 @@
+//! Conversion between days since the epoch, which the database stores, and calendar dates.
+
+use crate::error::Result;
+
+/// Formats a day as an ISO 8601 date, like `2024-02-29`.
+pub fn format(epoch_days: u16) -> String {
+    let (year, month, day) = civil_from_days(i64::from(epoch_days));
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Parses an ISO 8601 date, like `2024-02-29`, into days since the epoch.
+pub fn parse(s: &str) -> Result<u16> {
+    let invalid = || format!("invalid date '{s}', expected YYYY-MM-DD");
+
+    let mut parts = s.splitn(3, '-');
+    let mut next = |len: usize| -> Result<i64> {
+        let part = parts
+            .next()
+            .filter(|p| p.len() == len)
+            .ok_or_else(invalid)?;
+        if !part.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid().into());
+        }
+
+        Ok(part.parse()?)
+    };
+    let (year, month, day) = (next(4)?, next(2)?, next(2)?);
+
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(invalid().into());
+    }
+
+    u16::try_from(days_from_civil(year, month, day))
+        .map_err(|_| format!("date '{s}' is outside of the supported range").into())
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// The algorithms below are from http://howardhinnant.github.io/date_algorithms.html. Years are
+// counted from March, so that leap days are at the end of them.
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = (month_from_march + 2) % 12 + 1;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_known_dates() {
+        assert_eq!(format(0), "1970-01-01");
+        assert_eq!(format(11_016), "2000-02-29");
+        assert_eq!(format(19_782), "2024-02-29");
+        assert_eq!(format(u16::MAX), "2149-06-06");
+    }
+
+    #[test]
+    fn parse_is_the_inverse_of_format() {
+        for days in (0..=u16::MAX).step_by(7) {
+            assert_eq!(parse(&format(days)).unwrap(), days);
+        }
+
+        assert!(parse("2023-02-29").is_err());
+        assert!(parse("2024-13-01").is_err());
+        assert!(parse("1969-12-31").is_err());
+        assert!(parse("2024-1-01").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Finding cards which have the same front, and merging them.
+
+use crate::error::Result;
+use crate::{CardId, CardIndex, CardSchedule, Filter, Srs, card_front, card_index, update};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A card with the same front as another, found by [matching].
+#[derive(Clone, Copy, Debug)]
+pub struct Duplicate {
+    pub index: CardIndex,
+    /// Whether the fronts are identical, rather than only the same after [normalise].
+    pub is_exact: bool,
+}
+
+/// Returns the form of a front used to compare it with others: lowercase, with runs of whitespace
+/// replaced by a single space, and no whitespace at either end.
+pub fn normalise(front: &str) -> String {
+    front
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the cards with the given front, either exactly or after [normalise]. Exact matches come
+/// first.
+pub fn matching(srs: &Srs, front: &str) -> Vec<Duplicate> {
+    let normalised = normalise(front);
+
+    let mut duplicates: Vec<Duplicate> = srs
+        .cards
+        .iter()
+        .enumerate()
+        .filter_map(|(i, card)| {
+            let existing = card_front(card).ok()?;
+            if existing == front {
+                Some((i, true))
+            } else if normalise(existing) == normalised {
+                Some((i, false))
+            } else {
+                None
+            }
+        })
+        .map(|(i, is_exact)| Duplicate {
+            index: i as CardIndex,
+            is_exact,
+        })
+        .collect();
+    duplicates.sort_by_key(|d| !d.is_exact);
+
+    duplicates
+}
+
+/// Returns the groups of cards matching the filter which have the same front after [normalise], in
+/// the order their first card is stored. Cards within a group are in the order they're stored.
+pub fn groups(srs: &Srs, filter: &Filter) -> Vec<Vec<CardIndex>> {
+    let mut groups: Vec<Vec<CardIndex>> = Vec::new();
+    let mut group_of_front: HashMap<String, usize> = HashMap::new();
+
+    for (i, card) in srs.cards.iter().enumerate() {
+        let i = i as CardIndex;
+        if !filter.matches(srs, i) {
+            continue;
+        }
+
+        let Ok(front) = card_front(card) else {
+            continue;
+        };
+
+        match group_of_front.get(&normalise(front)) {
+            Some(&group) => groups[group].push(i),
+            None => {
+                group_of_front.insert(normalise(front), groups.len());
+                groups.push(vec![i]);
+            }
+        }
+    }
+
+    groups.retain(|g| g.len() > 1);
+
+    groups
+}
+
+/// Returns the card out of the given ones with the most mature schedule: the longest interval out
+/// of the ones which aren't waiting to be relearned, or the longest interval overall when they all
+/// are. Ties go to the first card.
+pub fn most_mature(srs: &Srs, indices: &[CardIndex]) -> Option<CardIndex> {
+    let maturity = |sched: &CardSchedule| (sched.scheduled_for != 0, sched.most_recent_interval);
+
+    indices
+        .iter()
+        .copied()
+        .rev()
+        .max_by_key(|&i| maturity(&srs.schedule[usize::from(i)]))
+}
+
+/// Merges the cards with the given IDs into the one with the [most_mature] schedule, and returns
+/// its ID. The kept card gets the tags of all of them, and the others are deleted. Their review
+/// history is kept, like it is for deleted cards.
+pub fn merge(srs: Srs, path: &Path, ids: &[CardId]) -> Result<CardId> {
+    let mut kept_id = None;
+
+    update(srs, path, |srs| {
+        let indices = ids
+            .iter()
+            .map(|&id| card_index(&srs, id))
+            .collect::<Result<Vec<_>>>()?;
+
+        let kept = most_mature(&srs, &indices).ok_or("no cards to merge")?;
+        kept_id = Some(srs.ids[usize::from(kept)]);
+
+        let mut card_tags = srs.card_tags.into_vec();
+        let mut tags = std::mem::take(&mut card_tags[usize::from(kept)]);
+        for &i in &indices {
+            for tag in &card_tags[usize::from(i)] {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+        card_tags[usize::from(kept)] = tags;
+
+        let srs = Srs {
+            card_tags: card_tags.into_boxed_slice(),
+            ..srs
+        };
+
+        Ok(crate::retain_cards(srs, |i| {
+            i == usize::from(kept) || !indices.contains(&(i as CardIndex))
+        }))
+    })?;
+
+    kept_id.ok_or_else(|| "no cards to merge".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalise_ignores_whitespace_and_case() {
+        assert_eq!(normalise("  Hello\n\tWORLD  "), "hello world");
+        assert_eq!(normalise("日本  語"), "日本 語");
+    }
+
+    fn srs(fronts: &[&str], intervals: &[(u16, u16)]) -> Srs {
+        let n = fronts.len();
+
+        Srs {
+            cards: fronts
+                .iter()
+                .map(|f| format!("{f}\0back").into_bytes())
+                .collect(),
+            schedule: intervals
+                .iter()
+                .map(|&(most_recent_interval, scheduled_for)| CardSchedule {
+                    most_recent_interval,
+                    scheduled_for,
+                    memory: Default::default(),
+                })
+                .collect(),
+            ids: (0..n as CardId).collect(),
+            next_id: n as CardId,
+            card_decks: vec![0; n].into_boxed_slice(),
+            card_tags: vec![Vec::new(); n].into_boxed_slice(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exact_matches_come_first() {
+        let srs = srs(&["a  b", "a b", "c"], &[(1, 1); 3]);
+
+        let duplicates = matching(&srs, "a b");
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[0].index, 1);
+        assert!(duplicates[0].is_exact);
+        assert_eq!(duplicates[1].index, 0);
+        assert!(!duplicates[1].is_exact);
+    }
+
+    #[test]
+    fn groups_by_normalised_front() {
+        let srs = srs(&["A", "b", "a ", "c", "B"], &[(1, 1); 5]);
+
+        assert_eq!(groups(&srs, &Filter::default()), [vec![0, 2], vec![1, 4]]);
+    }
+
+    #[test]
+    fn most_mature_prefers_cards_not_being_relearned() {
+        let srs = srs(&["a", "a", "a"], &[(5, 10), (30, 0), (5, 12)]);
+
+        assert_eq!(most_mature(&srs, &[0, 1, 2]), Some(0));
+        assert_eq!(most_mature(&srs, &[1]), Some(1));
+    }
+}
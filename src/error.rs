@@ -1,3 +1,20 @@
 use std::error::Error;
+use std::fmt;
 
 pub type Result<T> = core::result::Result<T, Box<dyn Error>>;
+
+/// Returned when writing the database would discard changes which another process made to it
+/// after it was read.
+#[derive(Debug)]
+pub struct Conflict;
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the database was changed by another process after it was read"
+        )
+    }
+}
+
+impl Error for Conflict {}
@@ -0,0 +1,646 @@
+//! Writing the contents of a database in formats other programs can read.
+//!
+//! JSON exports contain everything in the database, and can be read back with [from_json] to get
+//! an identical database. Days are written as dates, and cards waiting to be relearned have a
+//! `due` date of null.
+//!
+//! CSV and TSV exports have a row for each card, with its ID, deck, front, back, tags, interval,
+//! due date, and the number of correct and wrong answers to it.
+
+use crate::error::Result;
+use crate::json::{self, Value};
+use crate::limits;
+use crate::scheduler::Memory;
+use crate::{
+    CardId, CardIndex, CardSchedule, DEFAULT_DECK, Deck, DeckId, Filter, Grade, Review, Settings,
+    Srs, Stat, Stats, date, encode_card, tags,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The version of the JSON layout, increased when it changes in a way older versions can't read.
+const JSON_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    pub const ALL: [Self; 3] = [Self::Json, Self::Csv, Self::Tsv];
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.to_string() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|f| f.to_string()).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// Writes the cards which match the filter in the given format. JSON exports also include every
+/// deck, and the review history of the cards. The history of deleted cards, and answers from
+/// before the history was kept, are only included when the filter is empty.
+pub fn write(out: &mut impl Write, srs: &Srs, filter: &Filter, format: Format) -> Result<()> {
+    match format {
+        Format::Json => writeln!(out, "{}", json::to_string_pretty(&to_json(srs, filter)?))?,
+        Format::Csv => write_delimited(out, srs, filter, ',')?,
+        Format::Tsv => write_delimited(out, srs, filter, '\t')?,
+    }
+
+    Ok(())
+}
+
+fn matching_cards(srs: &Srs, filter: &Filter) -> impl Iterator<Item = CardIndex> {
+    (0..srs.cards.len() as CardIndex).filter(move |&i| filter.matches(srs, i))
+}
+
+fn due_date(sched: &CardSchedule) -> Option<String> {
+    (sched.scheduled_for != 0).then(|| date::format(sched.scheduled_for))
+}
+
+fn write_delimited(
+    out: &mut impl Write,
+    srs: &Srs,
+    filter: &Filter,
+    delimiter: char,
+) -> Result<()> {
+    let mut answers: HashMap<CardId, Stat> = HashMap::new();
+    for review in &srs.history {
+        let stat = answers.entry(review.card_id).or_default();
+        if review.grade.is_correct() {
+            stat.correct += 1;
+        } else {
+            stat.wrong += 1;
+        }
+    }
+
+    let field = |s: &str| {
+        if s.contains(['"', '\n', '\r', delimiter]) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    };
+
+    let header = [
+        "id", "deck", "front", "back", "tags", "interval", "due", "correct", "wrong",
+    ];
+    writeln!(out, "{}", header.join(&delimiter.to_string()))?;
+
+    for i in matching_cards(srs, filter) {
+        let i_usize = usize::from(i);
+        let card = crate::card(srs, i)?;
+        let id = srs.ids[i_usize];
+        let sched = &srs.schedule[i_usize];
+        let deck = deck_name(srs, srs.card_decks[i_usize]);
+        let stat = answers.get(&id).copied().unwrap_or_default();
+
+        let row = [
+            id.to_string(),
+            field(deck),
+            field(&card.front),
+            field(&card.back),
+            field(&srs.card_tags[i_usize].join(" ")),
+            sched.most_recent_interval.to_string(),
+            due_date(sched).unwrap_or_default(),
+            stat.correct.to_string(),
+            stat.wrong.to_string(),
+        ];
+        writeln!(out, "{}", row.join(&delimiter.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn deck_name(srs: &Srs, id: DeckId) -> &str {
+    srs.decks
+        .iter()
+        .find(|d| d.id == id)
+        .map_or("", |d| d.name.as_str())
+}
+
+fn settings_json(settings: &Settings) -> Value {
+    let limit = |limit: Option<u16>| limit.map_or(Value::Null, Value::number);
+
+    Value::object([
+        ("scheduler", Value::string(&settings.scheduler.to_string())),
+        ("review_limit", limit(settings.review_limit)),
+        ("new_limit", limit(settings.new_limit)),
+        (
+            "overflow_order",
+            Value::string(&settings.overflow_order.to_string()),
+        ),
+        ("load_balance", Value::Bool(settings.load_balance)),
+        ("max_interval", limit(settings.max_interval)),
+    ])
+}
+
+/// Returns the rows of stats with at least one answer.
+fn stats_json(stats: &Stats) -> Value {
+    Value::Array(
+        stats
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.correct > 0 || s.wrong > 0)
+            .map(|(interval, s)| {
+                Value::object([
+                    ("interval", Value::number(interval)),
+                    ("correct", Value::number(s.correct)),
+                    ("wrong", Value::number(s.wrong)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn to_json(srs: &Srs, filter: &Filter) -> Result<Value> {
+    let decks = srs
+        .decks
+        .iter()
+        .map(|deck| {
+            Value::object([
+                ("id", Value::number(deck.id)),
+                ("name", Value::string(&deck.name)),
+                (
+                    "settings",
+                    deck.settings.as_ref().map_or(Value::Null, settings_json),
+                ),
+            ])
+        })
+        .collect();
+
+    let mut ids = HashSet::new();
+    let mut cards = Vec::new();
+    for i in matching_cards(srs, filter) {
+        let i_usize = usize::from(i);
+        let card = crate::card(srs, i)?;
+        let sched = &srs.schedule[i_usize];
+        ids.insert(srs.ids[i_usize]);
+
+        cards.push(Value::object([
+            ("id", Value::number(srs.ids[i_usize])),
+            ("deck", Value::number(srs.card_decks[i_usize])),
+            ("front", Value::string(&card.front)),
+            ("back", Value::string(&card.back)),
+            (
+                "tags",
+                Value::Array(
+                    srs.card_tags[i_usize]
+                        .iter()
+                        .map(|t| Value::string(t))
+                        .collect(),
+                ),
+            ),
+            (
+                "schedule",
+                Value::object([
+                    ("interval", Value::number(sched.most_recent_interval)),
+                    ("due", due_date(sched).map_or(Value::Null, Value::String)),
+                    ("ease", Value::number(sched.memory.ease)),
+                    ("stability", Value::number(sched.memory.stability)),
+                    ("difficulty", Value::number(sched.memory.difficulty)),
+                ]),
+            ),
+        ]));
+    }
+
+    let history = srs
+        .history
+        .iter()
+        .filter(|review| filter.is_empty() || ids.contains(&review.card_id))
+        .map(|review| {
+            Value::object([
+                ("card_id", Value::number(review.card_id)),
+                ("date", Value::string(&date::format(review.day))),
+                ("previous_interval", Value::number(review.previous_interval)),
+                ("new_interval", Value::number(review.new_interval)),
+                ("grade", Value::string(&review.grade.to_string())),
+            ])
+        })
+        .collect();
+
+    let legacy_stats = if filter.is_empty() {
+        stats_json(&srs.legacy_stats)
+    } else {
+        Value::Array(Vec::new())
+    };
+
+    Ok(Value::object([
+        ("version", Value::number(JSON_VERSION)),
+        ("next_id", Value::number(srs.next_id)),
+        ("settings", settings_json(&srs.settings)),
+        (
+            "vacation_start",
+            srs.vacation_start
+                .map_or(Value::Null, |day| Value::string(&date::format(day))),
+        ),
+        ("shifted_days", Value::number(srs.shifted_days)),
+        ("decks", Value::Array(decks)),
+        ("cards", Value::Array(cards)),
+        ("history", Value::Array(history)),
+        ("legacy_stats", legacy_stats),
+        ("stats", stats_json(&crate::stats(srs, filter))),
+    ]))
+}
+
+/// Reads a database from a JSON export. The `stats` in the export are ignored, since they're
+/// calculated from the history and legacy stats.
+pub fn from_json(text: &str) -> Result<Srs> {
+    let root = json::parse(text)?;
+
+    let version: u8 = root.get("version")?.as_number()?;
+    if version != JSON_VERSION {
+        return Err(format!("unsupported export version {version}").into());
+    }
+
+    let next_id: CardId = field(&root, "next_id", |v| v.as_number())?;
+    let settings = field(&root, "settings", settings_from_json)?;
+    // These weren't in the first exports.
+    let vacation_start = optional_field(&root, "vacation_start", |v| date::parse(v.as_str()?))?;
+    let shifted_days = optional_field(&root, "shifted_days", |v| v.as_number())?.unwrap_or(0);
+
+    let mut decks = Vec::new();
+    for (i, deck) in root.get("decks")?.as_array()?.iter().enumerate() {
+        let deck = in_context(format!("deck {}", i + 1), || {
+            Ok(Deck {
+                id: field(deck, "id", |v| v.as_number())?,
+                name: field(deck, "name", |v| Ok(v.as_str()?.to_string()))?,
+                settings: field(deck, "settings", |v| {
+                    (!v.is_null()).then(|| settings_from_json(v)).transpose()
+                })?,
+            })
+        })?;
+        if deck.name.is_empty() || deck.name.len() > crate::MAX_DECK_NAME_LEN {
+            return Err(format!("deck {}: its name is empty or too long", deck.id).into());
+        }
+
+        if decks
+            .iter()
+            .any(|d: &Deck| d.id == deck.id || d.name == deck.name)
+        {
+            return Err(
+                format!("deck {} '{}' is listed more than once", deck.id, deck.name).into(),
+            );
+        }
+        decks.push(deck);
+    }
+    if !decks.iter().any(|d| d.id == DEFAULT_DECK) {
+        return Err(format!("the default deck (ID {DEFAULT_DECK}) is missing").into());
+    }
+
+    let json_cards = root.get("cards")?.as_array()?;
+    if json_cards.len() > crate::MAX_CARD_COUNT {
+        return Err(format!("there can't be more than {} cards", crate::MAX_CARD_COUNT).into());
+    }
+
+    let mut cards = Vec::with_capacity(json_cards.len());
+    let mut schedule = Vec::with_capacity(json_cards.len());
+    let mut ids = Vec::with_capacity(json_cards.len());
+    let mut card_decks = Vec::with_capacity(json_cards.len());
+    let mut card_tags = Vec::with_capacity(json_cards.len());
+
+    for (i, card) in json_cards.iter().enumerate() {
+        let id: CardId = in_context(format!("card {}", i + 1), || {
+            field(card, "id", |v| v.as_number())
+        })?;
+
+        in_context(format!("card {id}"), || {
+            if id >= next_id || ids.contains(&id) {
+                return Err("its ID is used more than once, or isn't less than next_id".into());
+            }
+
+            let deck: DeckId = field(card, "deck", |v| v.as_number())?;
+            if !decks.iter().any(|d| d.id == deck) {
+                return Err(format!("deck {deck} doesn't exist").into());
+            }
+
+            let front = field(card, "front", |v| Ok(v.as_str()?.to_string()))?;
+            if front.is_empty() {
+                return Err("its front is empty".into());
+            }
+            let back = field(card, "back", |v| Ok(v.as_str()?.to_string()))?;
+
+            let tags = field(card, "tags", |v| {
+                let tags = v
+                    .as_array()?
+                    .iter()
+                    .map(|t| t.as_str())
+                    .collect::<Result<Vec<_>>>()?;
+                tags::parse_list(&tags.join(" "))
+            })?;
+
+            cards.push(encode_card(&front, &back)?);
+            schedule.push(field(card, "schedule", schedule_from_json)?);
+            ids.push(id);
+            card_decks.push(deck);
+            card_tags.push(tags);
+
+            Ok(())
+        })?;
+    }
+
+    let history = root
+        .get("history")?
+        .as_array()?
+        .iter()
+        .enumerate()
+        .map(|(i, review)| {
+            in_context(format!("history entry {}", i + 1), || {
+                Ok(Review {
+                    card_id: field(review, "card_id", |v| v.as_number())?,
+                    day: field(review, "date", |v| date::parse(v.as_str()?))?,
+                    previous_interval: field(review, "previous_interval", |v| v.as_number())?,
+                    new_interval: field(review, "new_interval", |v| v.as_number())?,
+                    grade: field(review, "grade", |v| {
+                        let name = v.as_str()?;
+                        Grade::ALL
+                            .into_iter()
+                            .find(|g| g.to_string() == name)
+                            .ok_or_else(|| format!("unknown grade '{name}'").into())
+                    })?,
+                })
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut legacy_stats = Box::new([Stat::default(); crate::STAT_ROW_COUNT]);
+    for row in root.get("legacy_stats")?.as_array()? {
+        in_context("legacy_stats".to_string(), || {
+            let interval: usize = field(row, "interval", |v| v.as_number())?;
+            let stat = legacy_stats
+                .get_mut(interval)
+                .ok_or_else(|| format!("interval {interval} is out of range"))?;
+            stat.correct = field(row, "correct", legacy_count)?;
+            stat.wrong = field(row, "wrong", legacy_count)?;
+
+            Ok(())
+        })?;
+    }
+
+    Ok(Srs {
+        cards: cards.into_boxed_slice(),
+        schedule: schedule.into_boxed_slice(),
+        ids: ids.into_boxed_slice(),
+        next_id,
+        history: history.into_boxed_slice(),
+        legacy_stats,
+        settings,
+        card_decks: card_decks.into_boxed_slice(),
+        decks: decks.into_boxed_slice(),
+        card_tags: card_tags.into_boxed_slice(),
+        vacation_start,
+        shifted_days,
+        generation: 0,
+    })
+}
+
+/// Reads a count from [Srs::legacy_stats]. They're stored in a byte each, so larger counts can't
+/// be imported without losing them.
+fn legacy_count(value: &Value) -> Result<u32> {
+    let count = value.as_number()?;
+    if count > u32::from(u8::MAX) {
+        return Err(format!("can't be more than {}", u8::MAX).into());
+    }
+    Ok(count)
+}
+
+/// Reads the member of an object with the given name, adding its name to any error.
+fn field<T>(value: &Value, name: &str, read: impl FnOnce(&Value) -> Result<T>) -> Result<T> {
+    read(value.get(name)?).map_err(|e| format!("'{name}': {e}").into())
+}
+
+/// Like [field], but returns None when the member is missing or null.
+fn optional_field<T>(
+    value: &Value,
+    name: &str,
+    read: impl FnOnce(&Value) -> Result<T>,
+) -> Result<Option<T>> {
+    match value.get(name) {
+        Ok(v) if !v.is_null() => field(value, name, read).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn in_context<T>(context: String, read: impl FnOnce() -> Result<T>) -> Result<T> {
+    read().map_err(|e| format!("{context}: {e}").into())
+}
+
+/// Reads settings. The ones added after the first exports are optional, and get their defaults
+/// when they're missing.
+fn settings_from_json(value: &Value) -> Result<Settings> {
+    let limit = |v: &Value| {
+        let limit = v.as_number()?;
+        if limit > limits::MAX_LIMIT {
+            return Err(format!("can't be more than {}", limits::MAX_LIMIT).into());
+        }
+        Ok(limit)
+    };
+
+    Ok(Settings {
+        scheduler: field(value, "scheduler", |v| Ok(v.as_str()?.parse()?))?,
+        review_limit: optional_field(value, "review_limit", limit)?,
+        new_limit: optional_field(value, "new_limit", limit)?,
+        overflow_order: optional_field(value, "overflow_order", |v| Ok(v.as_str()?.parse()?))?
+            .unwrap_or_default(),
+        load_balance: optional_field(value, "load_balance", Value::as_bool)?.unwrap_or_default(),
+        max_interval: optional_field(value, "max_interval", |v| match limit(v)? {
+            0 => Err("can't be 0".into()),
+            days => Ok(days),
+        })?,
+    })
+}
+
+fn schedule_from_json(value: &Value) -> Result<CardSchedule> {
+    let most_recent_interval: u16 = field(value, "interval", |v| v.as_number())?;
+    if most_recent_interval == 0 {
+        return Err("'interval' can't be 0".into());
+    }
+
+    Ok(CardSchedule {
+        most_recent_interval,
+        scheduled_for: field(value, "due", |v| {
+            if v.is_null() {
+                Ok(0)
+            } else {
+                date::parse(v.as_str()?)
+            }
+        })?,
+        memory: Memory {
+            ease: field(value, "ease", |v| v.as_number())?,
+            stability: field(value, "stability", |v| v.as_number())?,
+            difficulty: field(value, "difficulty", |v| v.as_number())?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::OverflowOrder;
+    use crate::scheduler::SchedulerKind;
+
+    fn encoded(srs: &Srs) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        crate::format::encode(&mut bytes, srs, 1).unwrap();
+        bytes
+    }
+
+    fn sample() -> Srs {
+        let mut legacy_stats = Box::new([Stat::default(); crate::STAT_ROW_COUNT]);
+        legacy_stats[5] = Stat {
+            correct: 3,
+            wrong: 1,
+        };
+
+        Srs {
+            cards: Box::new([
+                encode_card("a \"quoted\"\nfront", "back, with a comma").unwrap(),
+                encode_card("日本語", "").unwrap(),
+            ]),
+            schedule: Box::new([
+                CardSchedule {
+                    most_recent_interval: 25,
+                    scheduled_for: 19_782,
+                    memory: Memory {
+                        ease: 2350,
+                        stability: 0.1 + 0.2,
+                        difficulty: 7.123_456_7,
+                    },
+                },
+                CardSchedule {
+                    most_recent_interval: 1,
+                    scheduled_for: 0,
+                    memory: Memory::default(),
+                },
+            ]),
+            ids: Box::new([3, 7]),
+            next_id: 9,
+            history: Box::new([
+                Review {
+                    card_id: 3,
+                    day: 19_757,
+                    previous_interval: 5,
+                    new_interval: 25,
+                    grade: Grade::Easy,
+                },
+                Review {
+                    card_id: 4,
+                    day: 19_758,
+                    previous_interval: 1,
+                    new_interval: 1,
+                    grade: Grade::Again,
+                },
+            ]),
+            legacy_stats,
+            settings: Settings {
+                scheduler: SchedulerKind::Fsrs,
+                review_limit: Some(150),
+                new_limit: Some(0),
+                overflow_order: OverflowOrder::LowestInterval,
+                load_balance: true,
+                max_interval: Some(36_500),
+            },
+            card_decks: Box::new([0, 2]),
+            decks: Box::new([
+                Deck {
+                    id: 0,
+                    name: "default".to_string(),
+                    settings: None,
+                },
+                Deck {
+                    id: 2,
+                    name: "japanese".to_string(),
+                    settings: Some(Settings {
+                        scheduler: SchedulerKind::Sm2,
+                        ..Default::default()
+                    }),
+                },
+            ]),
+            card_tags: Box::new([vec!["x".to_string(), "y".to_string()], Vec::new()]),
+            vacation_start: Some(19_790),
+            shifted_days: 12,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let srs = sample();
+
+        let mut json = Vec::new();
+        write(&mut json, &srs, &Filter::default(), Format::Json).unwrap();
+        let imported = from_json(str::from_utf8(&json).unwrap()).unwrap();
+
+        assert_eq!(encoded(&imported), encoded(&srs));
+    }
+
+    #[test]
+    fn filtered_json_only_has_matching_cards() {
+        let srs = sample();
+        let filter = Filter {
+            deck: Some(2),
+            tags: None,
+        };
+
+        let mut json = Vec::new();
+        write(&mut json, &srs, &filter, Format::Json).unwrap();
+        let imported = from_json(str::from_utf8(&json).unwrap()).unwrap();
+
+        assert_eq!(*imported.ids, [7]);
+        assert_eq!(imported.decks.len(), 2);
+        assert!(imported.history.is_empty());
+        assert!(imported.legacy_stats.iter().all(|s| s.correct == 0));
+    }
+
+    #[test]
+    fn legacy_stats_over_a_byte_are_rejected() {
+        let mut json = Vec::new();
+        write(&mut json, &sample(), &Filter::default(), Format::Json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        let row = r#""correct": 3"#;
+        assert!(json.contains(row), "{json}");
+        let json = json.replace(row, r#""correct": 256"#);
+
+        let Err(err) = from_json(&json) else {
+            panic!("imported a count which can't be stored");
+        };
+        assert!(err.to_string().contains("can't be more than 255"), "{err}");
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let mut csv = Vec::new();
+        write(&mut csv, &sample(), &Filter::default(), Format::Csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,deck,front,back,tags,interval,due,correct,wrong\n\
+             3,default,\"a \"\"quoted\"\"\nfront\",\"back, with a comma\",x y,25,2024-02-29,1,0\n\
+             7,japanese,日本語,,,1,,0,0\n"
+        );
+    }
+}
@@ -0,0 +1,1372 @@
+//! The on-disk layout of the database file, and upgrades from older versions of it.
+//!
+//! Every file starts with a fixed header:
+//!
+//! | Bytes | Contents                        |
+//! |-------|---------------------------------|
+//! | 4     | [MAGIC]                         |
+//! | 2     | format version, little endian   |
+//!
+//! Files written before the header was introduced are treated as version 0. Since version 2, the
+//! header is followed by a sequence of sections, each laid out as:
+//!
+//! | Bytes    | Contents                                   |
+//! |----------|--------------------------------------------|
+//! | 4        | tag identifying the section, e.g. `CARD`   |
+//! | 4        | payload length in bytes, little endian     |
+//! | variable | payload                                    |
+//! | 4        | CRC-32 of the tag and payload              |
+//!
+//! The sections in the current version are:
+//!
+//! - `HEAD`: the number of cards (u16), the generation (u32), then a row of two u8 counters
+//!   (correct, wrong) for each of the 365 stats buckets. The counters are no longer updated since
+//!   the `REVW` section was added.
+//! - `SCHD`: a row for each card: most recent interval (u16), scheduled for (u16), then the
+//!   scheduler's memory of the card: ease (u16), stability (f32), and difficulty (f32).
+//! - `CARD`: each card as a u16 length followed by its bytes.
+//! - `IDS `: the next card ID to assign (u32), followed by the ID of each card (u32).
+//! - `REVW`: a row for each review in the history: card ID (u32), day (u16), previous interval
+//!   (u16), new interval (u16), and the [Grade] of the answer (u8).
+//! - `CONF`: the settings of the database: the scheduler (u8), the daily review limit (u16), the
+//!   daily new card limit (u16), the [OverflowOrder] (u8), whether to balance the load of due dates
+//!   (u8, 0 or 1), then the maximum interval (u16). Limits and the maximum interval are [u16::MAX]
+//!   when there's none.
+//! - `DECK`: each deck as its ID (u16), the length of its name (u8), its name, the length of its
+//!   settings (u8), then its settings in the same layout as `CONF`. The length of the settings is
+//!   0 when the deck uses the settings of the database.
+//! - `CDCK`: the deck ID of each card (u16).
+//! - `TAGS`: for each card, the number of tags it has (u8), followed by each tag as a u8 length and
+//!   its bytes.
+//! - `DEFR`: the day vacation mode was turned on (u16, 0 when it's off), then the total number of
+//!   days due dates have been pushed back by (u16).
+//!
+//! All integers are little endian.
+
+use crate::error::Result;
+use crate::limits::OverflowOrder;
+use crate::scheduler::{Memory, SchedulerKind};
+use crate::{
+    CardSchedule, DEFAULT_DECK, Deck, Grade, Problem, Review, STAT_ROW_COUNT, Settings, Srs, Stat,
+};
+use std::collections::HashSet;
+use std::io::Write;
+use std::str;
+
+/// Identifies a file as an srs-cli database.
+///
+/// Version 0 files have no header, and start with the card count followed by the stats table.
+/// The first row of the stats table is always empty since intervals are never 0, so bytes 2 and 3
+/// of a version 0 file are always zero. That means they can't be mistaken for this signature.
+pub const MAGIC: [u8; 4] = *b"\x89SRS";
+
+/// The version of the layout written by [crate::write].
+pub const VERSION: u16 = 13;
+
+pub const HEADER_BYTES: usize = MAGIC.len() + 2;
+
+const HEAD: [u8; 4] = *b"HEAD";
+const SCHEDULE: [u8; 4] = *b"SCHD";
+const CARDS: [u8; 4] = *b"CARD";
+const IDS: [u8; 4] = *b"IDS ";
+const HISTORY: [u8; 4] = *b"REVW";
+const SETTINGS: [u8; 4] = *b"CONF";
+const DECKS: [u8; 4] = *b"DECK";
+const CARD_DECKS: [u8; 4] = *b"CDCK";
+const TAGS: [u8; 4] = *b"TAGS";
+const DEFERRAL: [u8; 4] = *b"DEFR";
+
+const NUM_CARDS_BYTES: usize = 2;
+const GENERATION_BYTES: usize = 4;
+const CARD_LENGTH_BYTES: usize = 2;
+const SCHEDULE_ROW_BYTES: usize = 14;
+/// The size of schedule rows before version 6 added the scheduler's memory.
+const LEGACY_SCHEDULE_ROW_BYTES: usize = 4;
+const ID_BYTES: usize = 4;
+const REVIEW_ROW_BYTES: usize = 11;
+const DECK_ID_BYTES: usize = 2;
+const STAT_ROW_BYTES: usize = 2;
+const STAT_BYTES: usize = STAT_ROW_BYTES * STAT_ROW_COUNT;
+/// Stored in place of a daily limit or maximum interval when there's none.
+const NO_LIMIT: u16 = u16::MAX;
+
+/// Upgrades a layout to the next version. The function at index `i` takes bytes in version `i`
+/// and returns them in version `i + 1`.
+type Migration = fn(Vec<u8>) -> Result<Vec<u8>>;
+
+const MIGRATIONS: [Migration; VERSION as usize] = [
+    v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9,
+    v9_to_v10, v10_to_v11, v11_to_v12, v12_to_v13,
+];
+
+/// Returns the header for files in the current version.
+pub fn header() -> [u8; HEADER_BYTES] {
+    header_for(VERSION)
+}
+
+fn header_for(version: u16) -> [u8; HEADER_BYTES] {
+    let mut header = [0; HEADER_BYTES];
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    header[MAGIC.len()..].copy_from_slice(&version.to_le_bytes());
+
+    header
+}
+
+/// Returns the format version of the given file contents.
+pub fn version(bytes: &[u8]) -> Result<u16> {
+    if !bytes.starts_with(&MAGIC) {
+        return Ok(0);
+    }
+
+    match bytes.get(MAGIC.len()..HEADER_BYTES) {
+        Some(v) => Ok(u16::from_le_bytes([v[0], v[1]])),
+        None => Err("file is too short to contain a format version".into()),
+    }
+}
+
+/// Converts the given file contents to the current version, applying each migration in order.
+/// The result starts with [header].
+pub fn upgrade(mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let version = version(&bytes)?;
+    if version > VERSION {
+        return Err(format!(
+            "file is in format version {version}, but this version of {} only supports up to {VERSION}",
+            env!("CARGO_PKG_NAME"),
+        )
+        .into());
+    }
+
+    for migration in &MIGRATIONS[usize::from(version)..] {
+        bytes = migration(bytes)?;
+    }
+
+    Ok(bytes)
+}
+
+/// Version 1 adds the header in front of the version 0 layout.
+fn v0_to_v1(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    Ok(header_for(1).into_iter().chain(bytes).collect())
+}
+
+/// Version 2 splits the body into checksummed sections. The version 1 body is the card count and
+/// stats table, followed by the schedule, followed by the cards, with no way to detect truncation
+/// or corruption.
+fn v1_to_v2(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(&bytes[HEADER_BYTES..], HEADER_BYTES);
+
+    let num_cards = usize::from(reader.u16("card count")?);
+    let stats = reader.take(STAT_BYTES, "stats")?;
+    let schedule = reader.take(num_cards * LEGACY_SCHEDULE_ROW_BYTES, "schedule")?;
+    let cards = reader.rest();
+
+    let head = [&bytes[HEADER_BYTES..][..NUM_CARDS_BYTES], stats].concat();
+
+    assemble(
+        2,
+        &[
+            (HEAD, head),
+            (SCHEDULE, schedule.to_vec()),
+            (CARDS, cards.to_vec()),
+        ],
+    )
+}
+
+/// Version 3 adds the generation to the `HEAD` section, following the card count.
+fn v2_to_v3(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let head = payload_mut(&mut sections, HEAD)?;
+    head.splice(NUM_CARDS_BYTES..NUM_CARDS_BYTES, 0u32.to_le_bytes());
+
+    assemble(3, &sections)
+}
+
+/// Version 4 adds the `IDS ` section. Cards are given IDs matching their current position, which is
+/// what they were identified by before.
+fn v3_to_v4(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let head = payload_mut(&mut sections, HEAD)?;
+    let num_cards = u16::from_le_bytes([head[0], head[1]]);
+
+    let mut ids = Vec::with_capacity(ID_BYTES * (usize::from(num_cards) + 1));
+    ids.extend_from_slice(&u32::from(num_cards).to_le_bytes());
+    for id in 0..u32::from(num_cards) {
+        ids.extend_from_slice(&id.to_le_bytes());
+    }
+    sections.push((IDS, ids));
+
+    assemble(4, &sections)
+}
+
+/// Version 5 adds the empty `REVW` section. The stats table in `HEAD` is kept as is since there's
+/// no way to recover the reviews that it counts.
+fn v4_to_v5(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+    sections.push((HISTORY, Vec::new()));
+
+    assemble(5, &sections)
+}
+
+/// Version 6 adds the scheduler's memory to each row in the `SCHD` section, and adds the `CONF`
+/// section with the scheduler that was used until then.
+fn v5_to_v6(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let schedule = payload_mut(&mut sections, SCHEDULE)?;
+    *schedule = schedule
+        .chunks(LEGACY_SCHEDULE_ROW_BYTES)
+        .flat_map(|row| {
+            row.iter()
+                .copied()
+                .chain([0; SCHEDULE_ROW_BYTES - LEGACY_SCHEDULE_ROW_BYTES])
+        })
+        .collect();
+
+    sections.push((SETTINGS, vec![SchedulerKind::Multiplier.to_byte()]));
+
+    assemble(6, &sections)
+}
+
+/// Version 7 replaces whether each answer in the `REVW` section was correct with its [Grade].
+/// Correct answers become [Grade::Good], and wrong ones become [Grade::Again].
+fn v6_to_v7(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let history = payload_mut(&mut sections, HISTORY)?;
+    for row in history.chunks_exact_mut(REVIEW_ROW_BYTES) {
+        let is_correct = row[REVIEW_ROW_BYTES - 1] != 0;
+        row[REVIEW_ROW_BYTES - 1] = if is_correct {
+            Grade::Good as u8
+        } else {
+            Grade::Again as u8
+        };
+    }
+
+    assemble(7, &sections)
+}
+
+/// Version 8 adds the `DECK` section containing the default deck, and the `CDCK` section with
+/// every card in it.
+fn v7_to_v8(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let head = payload_mut(&mut sections, HEAD)?;
+    let num_cards = usize::from(u16::from_le_bytes([head[0], head[1]]));
+
+    let name = b"default";
+    let mut decks = DEFAULT_DECK.to_le_bytes().to_vec();
+    decks.push(name.len() as u8);
+    decks.extend_from_slice(name);
+    decks.push(0);
+    sections.push((DECKS, decks));
+
+    sections.push((CARD_DECKS, DEFAULT_DECK.to_le_bytes().repeat(num_cards)));
+
+    assemble(8, &sections)
+}
+
+/// Version 9 adds the `TAGS` section, with no tags on any card.
+fn v8_to_v9(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let head = payload_mut(&mut sections, HEAD)?;
+    let num_cards = usize::from(u16::from_le_bytes([head[0], head[1]]));
+
+    sections.push((TAGS, vec![0; num_cards]));
+
+    assemble(9, &sections)
+}
+
+/// Version 10 adds the daily limits and overflow order to the settings. Existing databases get no
+/// limits.
+fn v9_to_v10(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+
+    let mut extension = [NO_LIMIT.to_le_bytes(), NO_LIMIT.to_le_bytes()].concat();
+    extension.push(OverflowOrder::default().to_byte());
+    extend_settings(&mut sections, &extension)?;
+
+    assemble(10, &sections)
+}
+
+/// Version 11 adds whether to balance the load of due dates to the settings, which is off for
+/// existing databases.
+fn v10_to_v11(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+    extend_settings(&mut sections, &[0])?;
+
+    assemble(11, &sections)
+}
+
+/// Version 12 adds the `DEFR` section, with vacation mode off and due dates never pushed back.
+fn v11_to_v12(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+    sections.push((DEFERRAL, vec![0; 4]));
+
+    assemble(12, &sections)
+}
+
+/// Version 13 adds the maximum interval to the settings. Existing databases get no maximum.
+fn v12_to_v13(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut sections = intact_sections(&bytes)?;
+    extend_settings(&mut sections, &NO_LIMIT.to_le_bytes())?;
+
+    assemble(13, &sections)
+}
+
+/// Appends bytes for new settings to the settings in `CONF`, and to those of each deck which has
+/// its own.
+fn extend_settings(sections: &mut [([u8; 4], Vec<u8>)], extension: &[u8]) -> Result<()> {
+    payload_mut(sections, SETTINGS)?.extend_from_slice(extension);
+
+    let decks = payload_mut(sections, DECKS)?;
+    let mut reader = Reader::new(decks, 0);
+    let mut upgraded = Vec::with_capacity(decks.len());
+    while !reader.is_empty() {
+        upgraded.extend_from_slice(reader.take(DECK_ID_BYTES, "deck ID")?);
+        let name_length = reader.take(1, "deck name length")?;
+        upgraded.extend_from_slice(name_length);
+        upgraded.extend_from_slice(reader.take(usize::from(name_length[0]), "deck name")?);
+
+        let settings_length = usize::from(reader.take(1, "deck settings length")?[0]);
+        let settings = reader.take(settings_length, "deck settings")?;
+        if settings.is_empty() {
+            upgraded.push(0);
+        } else {
+            upgraded.push((settings_length + extension.len()) as u8);
+            upgraded.extend_from_slice(settings);
+            upgraded.extend_from_slice(extension);
+        }
+    }
+    *decks = upgraded;
+
+    Ok(())
+}
+
+/// Returns the tag and a copy of the payload of each section in the given file contents, for a
+/// migration to modify.
+fn intact_sections(bytes: &[u8]) -> Result<Vec<([u8; 4], Vec<u8>)>> {
+    sections(bytes)?
+        .into_iter()
+        .map(|s| {
+            if s.is_intact {
+                Ok((s.tag, s.payload.to_vec()))
+            } else {
+                Err(format!(
+                    "checksum mismatch in {} section at byte {}",
+                    tag_str(s.tag),
+                    s.offset,
+                )
+                .into())
+            }
+        })
+        .collect()
+}
+
+fn payload_mut(sections: &mut [([u8; 4], Vec<u8>)], tag: [u8; 4]) -> Result<&mut Vec<u8>> {
+    sections
+        .iter_mut()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, payload)| payload)
+        .ok_or_else(|| format!("missing {} section", tag_str(tag)).into())
+}
+
+/// Builds file contents in the given version out of sections.
+fn assemble(version: u16, sections: &[([u8; 4], Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut out = header_for(version).to_vec();
+    for (tag, payload) in sections {
+        write_section(&mut out, *tag, payload)?;
+    }
+
+    Ok(out)
+}
+
+/// Writes an [Srs] in the current version, including the header. `generation` is written in
+/// place of the one in `srs`.
+pub fn encode(out: &mut impl Write, srs: &Srs, generation: u32) -> Result<()> {
+    let Srs {
+        cards,
+        schedule,
+        ids,
+        next_id,
+        history,
+        legacy_stats,
+        settings,
+        card_decks,
+        decks,
+        card_tags,
+        vacation_start,
+        shifted_days,
+        ..
+    } = srs;
+
+    let num_cards: u16 = cards
+        .len()
+        .try_into()
+        .map_err(|_| format!("too many cards to write: {}", cards.len()))?;
+
+    out.write_all(&header())?;
+
+    let mut head = Vec::with_capacity(NUM_CARDS_BYTES + GENERATION_BYTES + STAT_BYTES);
+    head.extend_from_slice(&num_cards.to_le_bytes());
+    head.extend_from_slice(&generation.to_le_bytes());
+    for stat in legacy_stats.iter() {
+        // These counts are never incremented, so they still fit in the u8s they were read from.
+        head.extend_from_slice(&[
+            u8::try_from(stat.correct).unwrap_or(u8::MAX),
+            u8::try_from(stat.wrong).unwrap_or(u8::MAX),
+        ]);
+    }
+    write_section(out, HEAD, &head)?;
+
+    let mut schedule_bytes = Vec::with_capacity(schedule.len() * SCHEDULE_ROW_BYTES);
+    for s in schedule.iter() {
+        schedule_bytes.extend_from_slice(&s.most_recent_interval.to_le_bytes());
+        schedule_bytes.extend_from_slice(&s.scheduled_for.to_le_bytes());
+        schedule_bytes.extend_from_slice(&s.memory.ease.to_le_bytes());
+        schedule_bytes.extend_from_slice(&s.memory.stability.to_le_bytes());
+        schedule_bytes.extend_from_slice(&s.memory.difficulty.to_le_bytes());
+    }
+    write_section(out, SCHEDULE, &schedule_bytes)?;
+
+    let mut cards_bytes =
+        Vec::with_capacity(cards.iter().map(|c| CARD_LENGTH_BYTES + c.len()).sum());
+    for card in cards.iter() {
+        let length: u16 = card
+            .len()
+            .try_into()
+            .map_err(|_| format!("card is too long to write: {} bytes", card.len()))?;
+        cards_bytes.extend_from_slice(&length.to_le_bytes());
+        cards_bytes.extend_from_slice(card);
+    }
+    write_section(out, CARDS, &cards_bytes)?;
+
+    let mut ids_bytes = Vec::with_capacity(ID_BYTES * (ids.len() + 1));
+    ids_bytes.extend_from_slice(&next_id.to_le_bytes());
+    for id in ids.iter() {
+        ids_bytes.extend_from_slice(&id.to_le_bytes());
+    }
+    write_section(out, IDS, &ids_bytes)?;
+
+    let mut history_bytes = Vec::with_capacity(history.len() * REVIEW_ROW_BYTES);
+    for review in history.iter() {
+        history_bytes.extend_from_slice(&review.card_id.to_le_bytes());
+        history_bytes.extend_from_slice(&review.day.to_le_bytes());
+        history_bytes.extend_from_slice(&review.previous_interval.to_le_bytes());
+        history_bytes.extend_from_slice(&review.new_interval.to_le_bytes());
+        history_bytes.push(review.grade as u8);
+    }
+    write_section(out, HISTORY, &history_bytes)?;
+
+    write_section(out, SETTINGS, &encode_settings(settings))?;
+
+    let mut decks_bytes = Vec::new();
+    for deck in decks.iter() {
+        let name_length: u8 = deck
+            .name
+            .len()
+            .try_into()
+            .map_err(|_| format!("deck name is too long to write: {:?}", deck.name))?;
+        decks_bytes.extend_from_slice(&deck.id.to_le_bytes());
+        decks_bytes.push(name_length);
+        decks_bytes.extend_from_slice(deck.name.as_bytes());
+
+        let settings = deck
+            .settings
+            .as_ref()
+            .map(encode_settings)
+            .unwrap_or_default();
+        decks_bytes.push(settings.len() as u8);
+        decks_bytes.extend_from_slice(&settings);
+    }
+    write_section(out, DECKS, &decks_bytes)?;
+
+    let mut card_decks_bytes = Vec::with_capacity(card_decks.len() * DECK_ID_BYTES);
+    for deck in card_decks.iter() {
+        card_decks_bytes.extend_from_slice(&deck.to_le_bytes());
+    }
+    write_section(out, CARD_DECKS, &card_decks_bytes)?;
+
+    let mut tags_bytes = Vec::new();
+    for tags in card_tags.iter() {
+        let count: u8 = tags
+            .len()
+            .try_into()
+            .map_err(|_| format!("too many tags to write: {}", tags.len()))?;
+        tags_bytes.push(count);
+
+        for tag in tags {
+            let length: u8 = tag
+                .len()
+                .try_into()
+                .map_err(|_| format!("tag is too long to write: {tag:?}"))?;
+            tags_bytes.push(length);
+            tags_bytes.extend_from_slice(tag.as_bytes());
+        }
+    }
+    write_section(out, TAGS, &tags_bytes)?;
+
+    let mut deferral_bytes = vacation_start.unwrap_or(0).to_le_bytes().to_vec();
+    deferral_bytes.extend_from_slice(&shifted_days.to_le_bytes());
+    write_section(out, DEFERRAL, &deferral_bytes)?;
+
+    Ok(())
+}
+
+/// Returns the payload of the `CONF` section for the given settings. Decks with their own settings
+/// store them in the same layout.
+fn encode_settings(settings: &Settings) -> Vec<u8> {
+    let mut bytes = vec![settings.scheduler.to_byte()];
+    bytes.extend_from_slice(&settings.review_limit.unwrap_or(NO_LIMIT).to_le_bytes());
+    bytes.extend_from_slice(&settings.new_limit.unwrap_or(NO_LIMIT).to_le_bytes());
+    bytes.push(settings.overflow_order.to_byte());
+    bytes.push(u8::from(settings.load_balance));
+    bytes.extend_from_slice(&settings.max_interval.unwrap_or(NO_LIMIT).to_le_bytes());
+
+    bytes
+}
+
+fn read_settings(reader: &mut Reader) -> Result<Settings> {
+    let offset = reader.offset;
+    let scheduler_byte = reader.take(1, "scheduler")?[0];
+    let scheduler = SchedulerKind::from_byte(scheduler_byte)
+        .ok_or_else(|| format!("unknown scheduler {scheduler_byte} at byte {offset}"))?;
+
+    let review_limit = Some(reader.u16("review limit")?).filter(|&l| l != NO_LIMIT);
+    let new_limit = Some(reader.u16("new card limit")?).filter(|&l| l != NO_LIMIT);
+
+    let offset = reader.offset;
+    let order_byte = reader.take(1, "overflow order")?[0];
+    let overflow_order = OverflowOrder::from_byte(order_byte)
+        .ok_or_else(|| format!("unknown overflow order {order_byte} at byte {offset}"))?;
+
+    let offset = reader.offset;
+    let load_balance = match reader.take(1, "load balancing")?[0] {
+        0 => false,
+        1 => true,
+        b => return Err(format!("invalid load balancing flag {b} at byte {offset}").into()),
+    };
+
+    let offset = reader.offset;
+    let max_interval = match reader.u16("maximum interval")? {
+        0 => return Err(format!("maximum interval of 0 days at byte {offset}").into()),
+        NO_LIMIT => None,
+        days => Some(days),
+    };
+
+    Ok(Settings {
+        scheduler,
+        review_limit,
+        new_limit,
+        overflow_order,
+        load_balance,
+        max_interval,
+    })
+}
+
+fn write_section(out: &mut impl Write, tag: [u8; 4], payload: &[u8]) -> Result<()> {
+    let length: u32 = payload
+        .len()
+        .try_into()
+        .map_err(|_| format!("section {} is too long to write", tag_str(tag)))?;
+
+    out.write_all(&tag)?;
+    out.write_all(&length.to_le_bytes())?;
+    out.write_all(payload)?;
+    out.write_all(&crc32(&[&tag, payload]).to_le_bytes())?;
+
+    Ok(())
+}
+
+/// Reads an [Srs] from file contents in the current version. Any truncation, inconsistent length,
+/// or checksum mismatch results in an error describing where the problem is.
+pub fn decode(bytes: &[u8]) -> Result<Srs> {
+    let sections = sections(bytes)?;
+
+    if let Some(s) = sections.iter().find(|s| !s.is_intact) {
+        return Err(format!(
+            "checksum mismatch in {} section at byte {}",
+            tag_str(s.tag),
+            s.offset,
+        )
+        .into());
+    }
+
+    Ok(parse(&sections)?.0)
+}
+
+/// Scans file contents in the current version for problems which [decode] doesn't catch, like
+/// cards which can't be split into a front and back. Problems which prevent the file from being
+/// read at all are returned as an error.
+pub fn check(bytes: &[u8], now_in_epoch_days: u16) -> Result<Vec<Problem>> {
+    let sections = sections(bytes)?;
+
+    let mut problems: Vec<Problem> = sections
+        .iter()
+        .filter(|s| !s.is_intact)
+        .map(|s| Problem {
+            offset: s.offset,
+            description: format!("checksum mismatch in {} section", tag_str(s.tag)),
+        })
+        .collect();
+
+    let (srs, offsets) = parse(&sections)?;
+
+    for (i, (sched, id)) in srs.schedule.iter().zip(&srs.ids).enumerate() {
+        let offset = offsets.schedule + i * SCHEDULE_ROW_BYTES;
+
+        if sched.most_recent_interval == 0 {
+            problems.push(Problem {
+                offset,
+                description: format!("card {id} has an interval of 0 days"),
+            });
+        }
+
+        // Cards can be due later than their interval from today once due dates have been pushed
+        // back.
+        let latest_day = now_in_epoch_days
+            .saturating_add(sched.most_recent_interval)
+            .saturating_add(srs.shifted_days);
+        if sched.scheduled_for > latest_day {
+            problems.push(Problem {
+                offset: offset + 2,
+                description: format!(
+                    "card {id} is scheduled for day {}, which is after day {latest_day} (today plus its interval of {} days and {} shifted days)",
+                    sched.scheduled_for, sched.most_recent_interval, srs.shifted_days,
+                ),
+            });
+        }
+    }
+
+    for ((card, &offset), id) in srs.cards.iter().zip(&offsets.cards).zip(&srs.ids) {
+        let Some(separator_idx) = card.iter().position(|&b| b == crate::Card::SEPARATOR) else {
+            problems.push(Problem {
+                offset,
+                description: format!("card {id} has no null separator between front and back"),
+            });
+            continue;
+        };
+
+        if separator_idx == 0 {
+            problems.push(Problem {
+                offset,
+                description: format!("card {id} has an empty front"),
+            });
+        }
+
+        let (front, back) = (&card[..separator_idx], &card[separator_idx + 1..]);
+        if let Err(e) = str::from_utf8(front) {
+            problems.push(Problem {
+                offset: offset + e.valid_up_to(),
+                description: format!("card {id} has invalid UTF-8 in its front: {e}"),
+            });
+        }
+        if let Err(e) = str::from_utf8(back) {
+            problems.push(Problem {
+                offset: offset + separator_idx + 1 + e.valid_up_to(),
+                description: format!("card {id} has invalid UTF-8 in its back: {e}"),
+            });
+        }
+    }
+
+    let mut seen = HashSet::with_capacity(srs.ids.len());
+    for (i, &id) in srs.ids.iter().enumerate() {
+        let offset = offsets.ids + i * ID_BYTES;
+
+        if !seen.insert(id) {
+            problems.push(Problem {
+                offset,
+                description: format!("card ID {id} is used by more than one card"),
+            });
+        }
+
+        if id >= srs.next_id {
+            problems.push(Problem {
+                offset,
+                description: format!(
+                    "card ID {id} isn't less than the next ID to assign, {}",
+                    srs.next_id,
+                ),
+            });
+        }
+    }
+
+    let mut deck_ids = HashSet::with_capacity(srs.decks.len());
+    let mut deck_names = HashSet::with_capacity(srs.decks.len());
+    for (deck, &offset) in srs.decks.iter().zip(&offsets.decks) {
+        if !deck_ids.insert(deck.id) {
+            problems.push(Problem {
+                offset,
+                description: format!("deck ID {} is used by more than one deck", deck.id),
+            });
+        }
+
+        if !deck_names.insert(deck.name.as_str()) {
+            problems.push(Problem {
+                offset,
+                description: format!("deck name '{}' is used by more than one deck", deck.name),
+            });
+        }
+    }
+
+    if !deck_ids.contains(&DEFAULT_DECK) {
+        problems.push(Problem {
+            offset: offsets.decks.first().copied().unwrap_or(offsets.card_decks),
+            description: format!("the default deck (ID {DEFAULT_DECK}) is missing"),
+        });
+    }
+
+    for (i, (deck, id)) in srs.card_decks.iter().zip(&srs.ids).enumerate() {
+        if !deck_ids.contains(deck) {
+            problems.push(Problem {
+                offset: offsets.card_decks + i * DECK_ID_BYTES,
+                description: format!("card {id} is in deck {deck}, which doesn't exist"),
+            });
+        }
+    }
+
+    problems.sort_by_key(|p| p.offset);
+
+    Ok(problems)
+}
+
+struct Section<'a> {
+    tag: [u8; 4],
+    /// The offset of the payload from the start of the file.
+    offset: usize,
+    payload: &'a [u8],
+    /// Whether the stored checksum matches the tag and payload.
+    is_intact: bool,
+}
+
+/// Splits the file contents following the header into sections.
+fn sections(bytes: &[u8]) -> Result<Vec<Section<'_>>> {
+    let mut reader = Reader::new(&bytes[HEADER_BYTES..], HEADER_BYTES);
+
+    let mut sections = Vec::with_capacity(3);
+    while !reader.is_empty() {
+        let tag: [u8; 4] = reader.take(4, "section tag")?.try_into().unwrap();
+        let what = tag_str(tag);
+
+        let length = reader.u32(&format!("{what} section length"))?;
+        let offset = reader.offset;
+        let payload = reader.take(length as usize, &format!("{what} section"))?;
+        let checksum = reader.u32(&format!("{what} section checksum"))?;
+
+        if sections.iter().any(|s: &Section| s.tag == tag) {
+            return Err(format!("duplicate {what} section at byte {}", offset - 8).into());
+        }
+
+        sections.push(Section {
+            tag,
+            offset,
+            payload,
+            is_intact: checksum == crc32(&[&tag, payload]),
+        });
+    }
+
+    Ok(sections)
+}
+
+/// The offsets of rows from the start of the file, to be able to report the location of problems.
+struct Offsets {
+    schedule: usize,
+    cards: Vec<usize>,
+    ids: usize,
+    decks: Vec<usize>,
+    card_decks: usize,
+}
+
+fn parse(sections: &[Section]) -> Result<(Srs, Offsets)> {
+    let find = |tag| {
+        sections
+            .iter()
+            .find(|s| s.tag == tag)
+            .ok_or_else(|| format!("missing {} section", tag_str(tag)))
+    };
+
+    let head = find(HEAD)?;
+    let mut reader = Reader::new(head.payload, head.offset);
+    let num_cards = usize::from(reader.u16("card count")?);
+    let generation = reader.u32("generation")?;
+
+    let mut stats = [Stat::default(); STAT_ROW_COUNT];
+    reader
+        .take(STAT_BYTES, "stats")?
+        .chunks_exact(STAT_ROW_BYTES)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            stats[i] = Stat {
+                correct: u32::from(chunk[0]),
+                wrong: u32::from(chunk[1]),
+            }
+        });
+    reader.expect_end("HEAD section")?;
+
+    let schedule_section = find(SCHEDULE)?;
+    if schedule_section.payload.len() != num_cards * SCHEDULE_ROW_BYTES {
+        return Err(format!(
+            "SCHD section at byte {} is {} bytes, but {num_cards} cards need {} bytes",
+            schedule_section.offset,
+            schedule_section.payload.len(),
+            num_cards * SCHEDULE_ROW_BYTES,
+        )
+        .into());
+    }
+
+    let schedule = schedule_section
+        .payload
+        .chunks_exact(SCHEDULE_ROW_BYTES)
+        .map(|chunk| CardSchedule {
+            most_recent_interval: u16::from_le_bytes([chunk[0], chunk[1]]),
+            scheduled_for: u16::from_le_bytes([chunk[2], chunk[3]]),
+            memory: Memory {
+                ease: u16::from_le_bytes([chunk[4], chunk[5]]),
+                stability: f32::from_le_bytes([chunk[6], chunk[7], chunk[8], chunk[9]]),
+                difficulty: f32::from_le_bytes([chunk[10], chunk[11], chunk[12], chunk[13]]),
+            },
+        })
+        .collect();
+
+    let cards_section = find(CARDS)?;
+    let mut reader = Reader::new(cards_section.payload, cards_section.offset);
+
+    let mut cards = Vec::with_capacity(num_cards);
+    let mut card_offsets = Vec::with_capacity(num_cards);
+    while !reader.is_empty() {
+        let i = cards.len();
+        let length = usize::from(reader.u16(&format!("length of card {i}"))?);
+
+        card_offsets.push(reader.offset);
+        cards.push(reader.take(length, &format!("card {i}"))?.to_vec());
+    }
+
+    if cards.len() != num_cards {
+        return Err(format!(
+            "CARD section at byte {} contains {} cards, but HEAD section says there are {num_cards}",
+            cards_section.offset,
+            cards.len(),
+        )
+        .into());
+    }
+
+    let ids_section = find(IDS)?;
+    let mut reader = Reader::new(ids_section.payload, ids_section.offset);
+    let next_id = reader.u32("next card ID")?;
+    if reader.bytes.len() != num_cards * ID_BYTES {
+        return Err(format!(
+            "IDS section at byte {} has {} bytes of IDs, but {num_cards} cards need {} bytes",
+            ids_section.offset,
+            reader.bytes.len(),
+            num_cards * ID_BYTES,
+        )
+        .into());
+    }
+    let ids_offset = reader.offset;
+    let ids = reader
+        .rest()
+        .chunks_exact(ID_BYTES)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+
+    let history_section = find(HISTORY)?;
+    if history_section.payload.len() % REVIEW_ROW_BYTES != 0 {
+        return Err(format!(
+            "REVW section at byte {} is {} bytes, which isn't a whole number of {REVIEW_ROW_BYTES} byte rows",
+            history_section.offset,
+            history_section.payload.len(),
+        )
+        .into());
+    }
+
+    let history = history_section
+        .payload
+        .chunks_exact(REVIEW_ROW_BYTES)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let grade = Grade::from_number(chunk[10]).ok_or_else(|| {
+                format!(
+                    "unknown grade {} at byte {}",
+                    chunk[10],
+                    history_section.offset + i * REVIEW_ROW_BYTES + 10,
+                )
+            })?;
+
+            Ok(Review {
+                card_id: u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+                day: u16::from_le_bytes([chunk[4], chunk[5]]),
+                previous_interval: u16::from_le_bytes([chunk[6], chunk[7]]),
+                new_interval: u16::from_le_bytes([chunk[8], chunk[9]]),
+                grade,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    let settings_section = find(SETTINGS)?;
+    let mut reader = Reader::new(settings_section.payload, settings_section.offset);
+    let settings = read_settings(&mut reader)?;
+    reader.expect_end("CONF section")?;
+
+    let decks_section = find(DECKS)?;
+    let mut reader = Reader::new(decks_section.payload, decks_section.offset);
+
+    let mut decks = Vec::new();
+    let mut deck_offsets = Vec::new();
+    while !reader.is_empty() {
+        let i = decks.len();
+        deck_offsets.push(reader.offset);
+
+        let id = reader.u16(&format!("ID of deck {i}"))?;
+
+        let name_length = usize::from(reader.take(1, &format!("name length of deck {id}"))?[0]);
+        let name_offset = reader.offset;
+        let name = str::from_utf8(reader.take(name_length, &format!("name of deck {id}"))?)
+            .map_err(|e| {
+                format!(
+                    "invalid UTF-8 in name of deck {id} at byte {}: {e}",
+                    name_offset + e.valid_up_to(),
+                )
+            })?;
+
+        let settings_length =
+            usize::from(reader.take(1, &format!("settings length of deck {id}"))?[0]);
+        let settings_offset = reader.offset;
+        let settings_bytes = reader.take(settings_length, &format!("settings of deck {id}"))?;
+        let settings = if settings_bytes.is_empty() {
+            None
+        } else {
+            let mut reader = Reader::new(settings_bytes, settings_offset);
+            let settings = read_settings(&mut reader)?;
+            reader.expect_end(&format!("settings of deck {id}"))?;
+            Some(settings)
+        };
+
+        decks.push(Deck {
+            id,
+            name: name.to_string(),
+            settings,
+        });
+    }
+
+    let card_decks_section = find(CARD_DECKS)?;
+    if card_decks_section.payload.len() != num_cards * DECK_ID_BYTES {
+        return Err(format!(
+            "CDCK section at byte {} is {} bytes, but {num_cards} cards need {} bytes",
+            card_decks_section.offset,
+            card_decks_section.payload.len(),
+            num_cards * DECK_ID_BYTES,
+        )
+        .into());
+    }
+
+    let card_decks = card_decks_section
+        .payload
+        .chunks_exact(DECK_ID_BYTES)
+        .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+        .collect();
+
+    let tags_section = find(TAGS)?;
+    let mut reader = Reader::new(tags_section.payload, tags_section.offset);
+
+    let mut card_tags = Vec::with_capacity(num_cards);
+    while !reader.is_empty() {
+        let i = card_tags.len();
+        let count = reader.take(1, &format!("tag count of card {i}"))?[0];
+
+        let mut tags = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let length = usize::from(reader.take(1, &format!("tag length of card {i}"))?[0]);
+            let offset = reader.offset;
+            let tag =
+                str::from_utf8(reader.take(length, &format!("tag of card {i}"))?).map_err(|e| {
+                    format!(
+                        "invalid UTF-8 in tag at byte {}: {e}",
+                        offset + e.valid_up_to(),
+                    )
+                })?;
+            tags.push(tag.to_string());
+        }
+
+        card_tags.push(tags);
+    }
+
+    if card_tags.len() != num_cards {
+        return Err(format!(
+            "TAGS section at byte {} contains tags for {} cards, but HEAD section says there are {num_cards}",
+            tags_section.offset,
+            card_tags.len(),
+        )
+        .into());
+    }
+
+    let deferral_section = find(DEFERRAL)?;
+    let mut reader = Reader::new(deferral_section.payload, deferral_section.offset);
+    let vacation_start = Some(reader.u16("vacation start")?).filter(|&day| day != 0);
+    let shifted_days = reader.u16("shifted days")?;
+    reader.expect_end("DEFR section")?;
+
+    let srs = Srs {
+        cards: cards.into_boxed_slice(),
+        schedule,
+        ids,
+        next_id,
+        history,
+        legacy_stats: Box::new(stats),
+        settings,
+        card_decks,
+        decks: decks.into_boxed_slice(),
+        card_tags: card_tags.into_boxed_slice(),
+        vacation_start,
+        shifted_days,
+        generation,
+    };
+
+    let offsets = Offsets {
+        schedule: schedule_section.offset,
+        cards: card_offsets,
+        ids: ids_offset,
+        decks: deck_offsets,
+        card_decks: card_decks_section.offset,
+    };
+
+    Ok((srs, offsets))
+}
+
+fn tag_str(tag: [u8; 4]) -> String {
+    String::from_utf8_lossy(&tag).into_owned()
+}
+
+/// Reads values from a slice, producing errors with the location in the file instead of
+/// panicking when there aren't enough bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    /// The offset of the start of `bytes` from the start of the file.
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, n: usize, what: &str) -> Result<&'a [u8]> {
+        if n > self.bytes.len() {
+            return Err(format!(
+                "unexpected end of file at byte {} reading {what}: needed {n} bytes but only {} remain",
+                self.offset,
+                self.bytes.len(),
+            )
+            .into());
+        }
+
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        self.offset += n;
+
+        Ok(taken)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = self.bytes;
+        self.offset += rest.len();
+        self.bytes = &[];
+
+        rest
+    }
+
+    fn u16(&mut self, what: &str) -> Result<u16> {
+        let b = self.take(2, what)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32> {
+        let b = self.take(4, what)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn expect_end(&self, what: &str) -> Result<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "unexpected {} trailing bytes at byte {} in {what}",
+                self.bytes.len(),
+                self.offset,
+            )
+            .into())
+        }
+    }
+}
+
+/// Computes the CRC-32 (as used by zlib and PNG) of the concatenation of the given slices.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+
+    let mut crc = u32::MAX;
+    for &b in parts.iter().flat_map(|p| p.iter()) {
+        crc = TABLE[((crc ^ u32::from(b)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v0_file() -> Vec<u8> {
+        let mut bytes = vec![1, 0];
+        bytes.extend(vec![0; STAT_BYTES]);
+        bytes.extend([5, 0, 10, 0]);
+        bytes.extend([3, 0, b'a', 0, b'b']);
+
+        bytes
+    }
+
+    #[test]
+    fn version_0_is_upgraded() {
+        let upgraded = upgrade(v0_file()).unwrap();
+        assert_eq!(version(&upgraded).unwrap(), VERSION);
+
+        let srs = decode(&upgraded).unwrap();
+        assert_eq!(&*srs.cards, &[b"a\0b".to_vec()]);
+        assert_eq!(srs.schedule[0].most_recent_interval, 5);
+        assert_eq!(srs.schedule[0].scheduled_for, 10);
+        assert_eq!(&*srs.card_decks, &[DEFAULT_DECK]);
+        assert_eq!(srs.decks.len(), 1);
+        assert_eq!(srs.decks[0].name, "default");
+    }
+
+    #[test]
+    fn current_version_is_unchanged() {
+        let bytes = upgrade(v0_file()).unwrap();
+
+        assert_eq!(upgrade(bytes.clone()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut bytes = header().to_vec();
+        bytes[MAGIC.len()..].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(upgrade(bytes).is_err());
+    }
+
+    #[test]
+    fn encode_then_decode() {
+        let srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+
+        assert_eq!(bytes, upgrade(v0_file()).unwrap());
+    }
+
+    #[test]
+    fn history_round_trips() {
+        let mut srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+        srs.history = Box::new([Review {
+            card_id: 0,
+            day: 10,
+            previous_interval: 5,
+            new_interval: 24,
+            grade: Grade::Hard,
+        }]);
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+
+        let review = decode(&bytes).unwrap().history[0];
+        assert_eq!(review.card_id, 0);
+        assert_eq!(review.day, 10);
+        assert_eq!(review.previous_interval, 5);
+        assert_eq!(review.new_interval, 24);
+        assert_eq!(review.grade, Grade::Hard);
+    }
+
+    #[test]
+    fn decks_round_trip() {
+        let mut srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+        let mut decks = srs.decks.into_vec();
+        decks.push(Deck {
+            id: 3,
+            name: "日本語".to_string(),
+            settings: Some(Settings {
+                scheduler: SchedulerKind::Fsrs,
+                review_limit: Some(200),
+                new_limit: Some(0),
+                overflow_order: OverflowOrder::LowestInterval,
+                load_balance: true,
+                max_interval: Some(3650),
+            }),
+        });
+        srs.decks = decks.into_boxed_slice();
+        srs.card_decks = Box::new([3]);
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+
+        let srs = decode(&bytes).unwrap();
+        assert_eq!(&*srs.card_decks, &[3]);
+        assert_eq!(srs.decks[0].settings.map(|s| s.scheduler), None);
+        assert_eq!(srs.decks[1].id, 3);
+        assert_eq!(srs.decks[1].name, "日本語");
+        let settings = srs.decks[1].settings.unwrap();
+        assert_eq!(settings.scheduler, SchedulerKind::Fsrs);
+        assert_eq!(settings.review_limit, Some(200));
+        assert_eq!(settings.new_limit, Some(0));
+        assert_eq!(settings.overflow_order, OverflowOrder::LowestInterval);
+        assert!(settings.load_balance);
+        assert_eq!(settings.max_interval, Some(3650));
+    }
+
+    #[test]
+    fn version_9_settings_get_the_defaults() {
+        let mut sections = intact_sections(&upgrade(v0_file()).unwrap()).unwrap();
+        sections.retain(|(tag, _)| *tag != DEFERRAL);
+        *payload_mut(&mut sections, SETTINGS).unwrap() = vec![SchedulerKind::Sm2.to_byte()];
+        let mut decks = DEFAULT_DECK.to_le_bytes().to_vec();
+        decks.extend([1, b'a', 1, SchedulerKind::Fsrs.to_byte()]);
+        decks.extend(5u16.to_le_bytes());
+        decks.extend([1, b'b', 0]);
+        *payload_mut(&mut sections, DECKS).unwrap() = decks;
+
+        let srs = decode(&upgrade(assemble(9, &sections).unwrap()).unwrap()).unwrap();
+        assert_eq!(srs.settings.scheduler, SchedulerKind::Sm2);
+        assert_eq!(srs.settings.review_limit, None);
+        assert_eq!(srs.settings.new_limit, None);
+        let settings = srs.decks[0].settings.unwrap();
+        assert_eq!(settings.scheduler, SchedulerKind::Fsrs);
+        assert_eq!(settings.review_limit, None);
+        assert_eq!(settings.overflow_order, OverflowOrder::MostOverdue);
+        assert!(!settings.load_balance);
+        assert_eq!(settings.max_interval, None);
+        assert_eq!(srs.decks[1].name, "b");
+        assert!(srs.decks[1].settings.is_none());
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        let mut srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+        assert!(srs.card_tags[0].is_empty());
+
+        srs.card_tags = Box::new([vec!["rust".to_string(), "k8s".to_string()]]);
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+
+        assert_eq!(&decode(&bytes).unwrap().card_tags[0], &["rust", "k8s"]);
+    }
+
+    #[test]
+    fn deferral_round_trips() {
+        let mut srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+        assert_eq!(srs.vacation_start, None);
+        assert_eq!(srs.shifted_days, 0);
+
+        srs.vacation_start = Some(20_000);
+        srs.shifted_days = 30;
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+
+        let srs = decode(&bytes).unwrap();
+        assert_eq!(srs.vacation_start, Some(20_000));
+        assert_eq!(srs.shifted_days, 30);
+    }
+
+    #[test]
+    fn check_allows_for_shifted_days() {
+        let mut srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+        // The card has an interval of 5 days.
+        srs.schedule[0].scheduled_for = 30;
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+        assert_eq!(check(&bytes, 10).unwrap().len(), 1);
+
+        srs.shifted_days = 15;
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+        assert!(check(&bytes, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_finds_cards_in_missing_decks() {
+        let mut srs = decode(&upgrade(v0_file()).unwrap()).unwrap();
+        srs.card_decks = Box::new([7]);
+
+        let mut bytes = Vec::new();
+        encode(&mut bytes, &srs, srs.generation).unwrap();
+
+        let problems = check(&bytes, 10).unwrap();
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].description.contains("deck 7"));
+        assert_eq!(&bytes[problems[0].offset..][..2], &7u16.to_le_bytes());
+    }
+
+    #[test]
+    fn truncation_is_an_error() {
+        let bytes = upgrade(v0_file()).unwrap();
+
+        for len in HEADER_BYTES + 1..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let mut bytes = upgrade(v0_file()).unwrap();
+        let back = bytes.windows(3).position(|w| w == b"a\0b").unwrap() + 2;
+        bytes[back] = b'c';
+
+        assert!(decode(&bytes).is_err());
+
+        let problems = check(&bytes, 10).unwrap();
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].description.contains("CARD"));
+    }
+
+    #[test]
+    fn check_finds_bad_cards() {
+        let mut bytes = v0_file();
+        let len = bytes.len();
+        bytes[len - 2] = b'x';
+        let bytes = upgrade(bytes).unwrap();
+
+        let problems = check(&bytes, 0).unwrap();
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[0].description.contains("day 10"));
+        assert!(problems[1].description.contains("separator"));
+        assert_eq!(&bytes[problems[1].offset..][..3], b"axb");
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xCBF4_3926);
+    }
+}
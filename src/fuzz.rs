@@ -0,0 +1,178 @@
+//! Choosing the interval of a card that was answered correctly. The interval from the scheduler is
+//! moved by up to 5% either way, which keeps cards that were added together from being reviewed
+//! together forever.
+
+use crate::Settings;
+use crate::rand::Rng;
+use std::collections::HashMap;
+
+/// Returns the interval to give a card which the scheduler gave the given interval, with fuzz
+/// applied the way the settings say, and no longer than their maximum interval.
+pub(crate) fn next_interval(
+    interval: u16,
+    now_in_epoch_days: u16,
+    settings: &Settings,
+    due_counts: &HashMap<u16, usize>,
+    rng: &mut Rng,
+) -> u16 {
+    let max_interval = settings.max_interval.unwrap_or(u16::MAX).max(1);
+    let interval = interval.min(max_interval);
+
+    let interval = if settings.load_balance {
+        balanced(interval, now_in_epoch_days, due_counts, rng)
+    } else {
+        random(interval, rng)
+    };
+
+    // Fuzz can take the interval past the maximum.
+    interval.min(max_interval)
+}
+
+/// Returns the most that the interval can be moved by in either direction.
+fn max_fuzz(interval: u16) -> u16 {
+    ((interval as f32) * 0.05).ceil() as u16
+}
+
+/// Returns the interval moved by a random amount within the fuzz window.
+fn random(interval: u16, rng: &mut Rng) -> u16 {
+    // Generate a number in -fuzz..=fuzz. This fuzz factor prevents cards from getting grouped
+    // together based on when they were added.
+    let fuzz = rng.u16(max_fuzz(interval));
+
+    let interval = if rng.bool() {
+        interval.saturating_add(fuzz)
+    } else {
+        interval.saturating_sub(fuzz)
+    };
+
+    interval.max(1)
+}
+
+/// Returns the interval within the fuzz window which makes the card due on the day with the fewest
+/// cards due, given how many are due on each day. Ties are broken randomly, so that cards answered
+/// together still spread out when the days around them are equally busy.
+fn balanced(
+    interval: u16,
+    now_in_epoch_days: u16,
+    due_counts: &HashMap<u16, usize>,
+    rng: &mut Rng,
+) -> u16 {
+    let interval = interval.max(1);
+    let max_fuzz = max_fuzz(interval);
+    let window = interval.saturating_sub(max_fuzz).max(1)..=interval.saturating_add(max_fuzz);
+
+    let count = |i: u16| {
+        due_counts
+            .get(&now_in_epoch_days.saturating_add(i))
+            .copied()
+            .unwrap_or(0)
+    };
+    let fewest = window.clone().map(count).min().unwrap_or(0);
+    let candidates: Vec<u16> = window.filter(|&i| count(i) == fewest).collect();
+
+    candidates[usize::from(rng.u16((candidates.len() - 1) as u16))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TODAY: u16 = 20_000;
+
+    #[test]
+    fn random_stays_within_the_window() {
+        let mut rng = Rng::with_seed(123_456_789);
+
+        for _ in 0..1000 {
+            assert!((95..=105).contains(&random(100, &mut rng)));
+            assert!((1..=2).contains(&random(1, &mut rng)));
+        }
+    }
+
+    #[test]
+    fn balanced_picks_the_least_busy_day() {
+        // The window for an interval of 40 is 38..=42.
+        let due_counts = HashMap::from([
+            (TODAY + 38, 9),
+            (TODAY + 39, 4),
+            (TODAY + 40, 12),
+            (TODAY + 41, 2),
+            (TODAY + 42, 7),
+            // Outside of the window.
+            (TODAY + 37, 0),
+        ]);
+
+        for seed in 1..100 {
+            let mut rng = Rng::with_seed(seed);
+            assert_eq!(balanced(40, TODAY, &due_counts, &mut rng), 41);
+        }
+    }
+
+    #[test]
+    fn balanced_breaks_ties_randomly() {
+        // The window for an interval of 20 is 19..=21, and only the middle day has cards due.
+        let due_counts = HashMap::from([(TODAY + 20, 3)]);
+
+        let mut rng = Rng::with_seed(234_567_891);
+        let intervals: Vec<u16> = (0..6)
+            .map(|_| balanced(20, TODAY, &due_counts, &mut rng))
+            .collect();
+
+        assert_eq!(intervals, [19, 19, 21, 21, 19, 19]);
+    }
+
+    #[test]
+    fn balanced_fills_in_days_as_cards_are_scheduled() {
+        let mut due_counts = HashMap::new();
+        let mut rng = Rng::with_seed(876_543_212);
+
+        for _ in 0..50 {
+            let interval = balanced(100, TODAY, &due_counts, &mut rng);
+            *due_counts.entry(TODAY + interval).or_default() += 1;
+        }
+
+        // 50 cards over the 11 days in the window leaves each day with 4 or 5.
+        assert_eq!(due_counts.len(), 11);
+        assert!(due_counts.values().all(|&count| (4..=5).contains(&count)));
+    }
+
+    #[test]
+    fn large_intervals_saturate() {
+        let mut rng = Rng::with_seed(345_678_912);
+        let lowest = u16::MAX - max_fuzz(u16::MAX);
+
+        for load_balance in [false, true] {
+            let settings = Settings {
+                load_balance,
+                ..Default::default()
+            };
+
+            for _ in 0..1000 {
+                let interval = next_interval(u16::MAX, TODAY, &settings, &HashMap::new(), &mut rng);
+                assert!(interval >= lowest, "{interval}");
+            }
+        }
+    }
+
+    #[test]
+    fn max_interval_caps_fuzzed_intervals() {
+        let mut rng = Rng::with_seed(456_789_123);
+
+        for load_balance in [false, true] {
+            let settings = Settings {
+                load_balance,
+                max_interval: Some(365),
+                ..Default::default()
+            };
+
+            for _ in 0..1000 {
+                // The window for an interval of 365 is 346..=384.
+                let interval = next_interval(60_000, TODAY, &settings, &HashMap::new(), &mut rng);
+                assert!((346..=365).contains(&interval), "{interval}");
+            }
+
+            let interval = next_interval(10, TODAY, &settings, &HashMap::new(), &mut rng);
+            assert!((9..=11).contains(&interval), "{interval}");
+        }
+    }
+}
@@ -0,0 +1,723 @@
+//! Reading cards in bulk from files.
+//!
+//! CSV and TSV files have a row for each card with its front, back, and optionally its tags,
+//! separated by tabs or commas. Fields can be quoted with `"` to include the separator,
+//! newlines, or `""` for a quote. A first row of `front,back` or `front,back,tags` is skipped as a
+//! header.
+//!
+//! JSON files are read as an export made with [crate::export], and replace the whole database.
+//!
+//! Text files contain blocks separated by lines of [DIVIDER], alternating between the front and
+//! back of each card, like the template used to edit cards. The last line of a back can list the
+//! card's tags after `tags:`.
+//!
+//! Anki's "Notes in Plain Text" exports are delimited like TSV files, with optional header lines
+//! like `#separator:tab`, `#html:true` and `#tags column:3`. The first two fields of each note are
+//! its front and back, and its other fields are ignored, as are the columns given by headers like
+//! `#deck column:`, apart from its tags. HTML is converted to plain text.
+
+use crate::error::Result;
+use crate::{Srs, encode_card, tags};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Separates the front and back of cards in the text format.
+pub const DIVIDER: &str = "----------";
+
+/// Introduces the list of tags at the end of the back of a card in the text format.
+pub const TAGS_PREFIX: &str = "tags:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Anki,
+    Csv,
+    Json,
+    Tsv,
+    Text,
+}
+
+impl Format {
+    pub const ALL: [Self; 5] = [Self::Anki, Self::Csv, Self::Json, Self::Tsv, Self::Text];
+
+    /// Guesses the format of a file from its extension, and whether it starts with the header
+    /// lines of an Anki export. Other files which aren't CSV, JSON or TSV are read as text.
+    pub fn detect(path: &Path, text: &str) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
+            Some("csv") => Self::Csv,
+            Some("json") => Self::Json,
+            Some("tsv" | "tab") => Self::Tsv,
+            _ if anki_header(text.lines().next().unwrap_or_default()).is_some() => Self::Anki,
+            _ => Self::Text,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Anki => "anki",
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Tsv => "tsv",
+            Self::Text => "text",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.to_string() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|f| f.to_string()).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// A card read from a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// The line of the file that the card starts on, starting from 1.
+    pub line: usize,
+    pub front: String,
+    pub back: String,
+    pub tags: Vec<String>,
+}
+
+/// A problem with the card starting on a line of a file.
+#[derive(Debug, PartialEq)]
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Reads the cards in the given file contents. Every card which can't be read results in an
+/// error, rather than stopping at the first one. JSON exports can't be read as separate cards, and
+/// are read with [crate::export::from_json] instead.
+pub fn parse(text: &str, format: Format) -> (Vec<Record>, Vec<LineError>) {
+    let text = text.replace("\r\n", "\n");
+
+    match format {
+        Format::Anki => parse_anki(&text),
+        Format::Csv => parse_delimited(&text, ','),
+        Format::Tsv => parse_delimited(&text, '\t'),
+        Format::Text => parse_text(&text),
+        Format::Json => (
+            Vec::new(),
+            vec![LineError {
+                line: 1,
+                message: "JSON exports can't be imported as separate cards".to_string(),
+            }],
+        ),
+    }
+}
+
+/// Returns an error for each record which can't be stored as a card. Fails if there isn't room in
+/// the database for all of them.
+pub fn check(srs: &Srs, records: &[Record]) -> Result<Vec<LineError>> {
+    crate::check_card_count(srs, records.len())?;
+
+    Ok(records
+        .iter()
+        .filter_map(|r| {
+            let message = if r.front.is_empty() {
+                "the front of the card is empty".to_string()
+            } else {
+                encode_card(&r.front, &r.back).err()?.to_string()
+            };
+
+            Some(LineError {
+                line: r.line,
+                message,
+            })
+        })
+        .collect())
+}
+
+/// Reads the cards given to `add` on stdin, which are in the text format. Returns an error for
+/// each card which can't be added, sorted by line, or a single error when no cards were given.
+/// Fails if there isn't room in the database for all of them.
+pub fn parse_new_cards(srs: &Srs, text: &str) -> Result<(Vec<Record>, Vec<LineError>)> {
+    let (records, mut errors) = parse(text, Format::Text);
+    errors.extend(check(srs, &records)?);
+    errors.sort_by_key(|e| e.line);
+
+    if records.is_empty() && errors.is_empty() {
+        errors.push(LineError {
+            line: 1,
+            message: "no cards were given".to_string(),
+        });
+    }
+
+    Ok((records, errors))
+}
+
+fn parse_delimited(text: &str, delimiter: char) -> (Vec<Record>, Vec<LineError>) {
+    let (rows, mut errors) = split_rows(text, delimiter, 1);
+    let mut records = Vec::with_capacity(rows.len());
+
+    for (i, (line, fields)) in rows.into_iter().enumerate() {
+        let is_header = i == 0
+            && matches!(
+                fields
+                    .iter()
+                    .map(|f| f.trim().to_lowercase())
+                    .collect::<Vec<_>>()
+                    .as_slice(),
+                [front, back] | [front, back, _] if front == "front" && back == "back"
+            );
+        if is_header {
+            continue;
+        }
+
+        let (front, back, tags) = match fields.as_slice() {
+            [front, back] => (front, back, ""),
+            [front, back, tags] => (front, back, tags.as_str()),
+            _ => {
+                errors.push(LineError {
+                    line,
+                    message: format!("expected 2 or 3 fields, but found {}", fields.len()),
+                });
+                continue;
+            }
+        };
+
+        match tags::parse_list(tags) {
+            Ok(tags) => records.push(Record {
+                line,
+                front: front.trim().to_string(),
+                back: back.trim().to_string(),
+                tags,
+            }),
+            Err(e) => errors.push(LineError {
+                line,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    errors.sort_by_key(|e| e.line);
+
+    (records, errors)
+}
+
+/// Splits delimited text into rows of fields, along with the line each row starts on, counting
+/// from `first_line`. Blank lines are skipped.
+fn split_rows(
+    text: &str,
+    delimiter: char,
+    first_line: usize,
+) -> (Vec<(usize, Vec<String>)>, Vec<LineError>) {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut chars = text.chars().peekable();
+    let mut line = first_line;
+
+    while chars.peek().is_some() {
+        let row_line = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut error = None;
+
+        loop {
+            // Read one field
+            let is_quoted = chars.peek() == Some(&'"');
+            if is_quoted {
+                chars.next();
+                let quote_line = line;
+                let mut is_closed = false;
+
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                            field.push('"');
+                        } else {
+                            is_closed = true;
+                            break;
+                        }
+                    } else {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        field.push(c);
+                    }
+                }
+
+                if !is_closed {
+                    error = Some(format!("quote opened on line {quote_line} is never closed"));
+                }
+            }
+
+            // Read up to the end of the field
+            let mut is_end_of_row = true;
+            for c in chars.by_ref() {
+                match c {
+                    '\n' => {
+                        line += 1;
+                        break;
+                    }
+                    c if c == delimiter => {
+                        is_end_of_row = false;
+                        break;
+                    }
+                    c if !is_quoted => field.push(c),
+                    c if c.is_whitespace() => {}
+                    c => {
+                        error.get_or_insert(format!(
+                            "unexpected {c:?} after the closing quote of field {}",
+                            fields.len() + 1
+                        ));
+                    }
+                }
+            }
+
+            fields.push(std::mem::take(&mut field));
+
+            if is_end_of_row {
+                break;
+            }
+        }
+
+        if let Some(message) = error {
+            errors.push(LineError {
+                line: row_line,
+                message,
+            });
+        } else if !(fields.len() == 1 && fields[0].trim().is_empty()) {
+            rows.push((row_line, fields));
+        }
+    }
+
+    (rows, errors)
+}
+
+/// Returns the name and value of a header line of an Anki export, like `#separator:tab`.
+fn anki_header(line: &str) -> Option<(&str, &str)> {
+    const NAMES: [&str; 8] = [
+        "separator",
+        "html",
+        "tags column",
+        "columns",
+        "deck column",
+        "notetype column",
+        "guid column",
+        "deck",
+    ];
+
+    let (name, value) = line.strip_prefix('#')?.split_once(':')?;
+    let name = name.trim();
+
+    NAMES.contains(&name).then_some((name, value.trim()))
+}
+
+fn parse_anki(text: &str) -> (Vec<Record>, Vec<LineError>) {
+    let mut separator = '\t';
+    let mut is_html = true;
+    let mut tags_column = None;
+    // The columns which hold information about notes, rather than their fields
+    let mut note_columns = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut body = text;
+    let mut line = 1;
+    while let Some((header, rest)) = body.split_once('\n')
+        && let Some((name, value)) = anki_header(header)
+    {
+        let error = match name {
+            "separator" => match anki_separator(value) {
+                Some(c) => {
+                    separator = c;
+                    None
+                }
+                None => Some(format!("unknown separator '{value}'")),
+            },
+            "html" => match value {
+                "true" => {
+                    is_html = true;
+                    None
+                }
+                "false" => {
+                    is_html = false;
+                    None
+                }
+                _ => Some(format!("expected true or false, but found '{value}'")),
+            },
+            "tags column" | "guid column" | "notetype column" | "deck column" => {
+                match value.parse::<usize>() {
+                    Ok(column) if column > 0 => {
+                        if name == "tags column" {
+                            tags_column = Some(column - 1);
+                        }
+                        note_columns.push(column - 1);
+                        None
+                    }
+                    _ => Some(format!("invalid {name} '{value}'")),
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(message) = error {
+            errors.push(LineError { line, message });
+        }
+
+        body = rest;
+        line += 1;
+    }
+
+    if !errors.is_empty() {
+        return (Vec::new(), errors);
+    }
+
+    let (rows, mut errors) = split_rows(body, separator, line);
+    let mut records = Vec::with_capacity(rows.len());
+
+    for (line, columns) in rows {
+        let tags = tags_column
+            .and_then(|c| columns.get(c))
+            .map_or(Ok(Vec::new()), |t| tags::parse_list(t));
+
+        let fields: Vec<&String> = columns
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !note_columns.contains(i))
+            .map(|(_, f)| f)
+            .collect();
+        if fields.len() < 2 {
+            errors.push(LineError {
+                line,
+                message: format!("expected at least 2 fields, but found {}", fields.len()),
+            });
+            continue;
+        }
+
+        let text = |field: &str| {
+            let text = if is_html {
+                html_to_text(field)
+            } else {
+                field.to_string()
+            };
+            text.trim().to_string()
+        };
+
+        match tags {
+            Ok(tags) => records.push(Record {
+                line,
+                front: text(fields[0]),
+                back: text(fields[1]),
+                tags,
+            }),
+            Err(e) => errors.push(LineError {
+                line,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    errors.sort_by_key(|e| e.line);
+
+    (records, errors)
+}
+
+/// Returns the character named by the `#separator:` header of an Anki export.
+fn anki_separator(name: &str) -> Option<char> {
+    match name {
+        "tab" | "Tab" => Some('\t'),
+        "comma" | "Comma" => Some(','),
+        "semicolon" | "Semicolon" => Some(';'),
+        "space" | "Space" => Some(' '),
+        "pipe" | "Pipe" => Some('|'),
+        "colon" | "Colon" => Some(':'),
+        _ => {
+            let mut chars = name.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        }
+    }
+}
+
+/// Converts the HTML that Anki uses in fields to plain text. Line breaks and blocks become
+/// newlines, other tags are removed, and entities are decoded.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<'
+            && let Some(end) = rest.find('>')
+        {
+            let tag = rest[1..end].trim().to_lowercase();
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default();
+
+            let is_opening = !tag.starts_with('/');
+            let starts_line = match name {
+                "br" => true,
+                "div" | "p" | "li" | "tr" => {
+                    is_opening && !text.is_empty() && !text.ends_with('\n')
+                }
+                _ => false,
+            };
+            if starts_line {
+                text.push('\n');
+            }
+
+            rest = &rest[end + 1..];
+        } else if c == '&'
+            && let Some(end) = rest.find(';').filter(|&end| end <= 12)
+            && let Some(decoded) = html_entity(&rest[1..end])
+        {
+            text.push(decoded);
+            rest = &rest[end + 1..];
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    text
+}
+
+fn html_entity(name: &str) -> Option<char> {
+    match name {
+        "nbsp" => Some(' '),
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+fn parse_text(text: &str) -> (Vec<Record>, Vec<LineError>) {
+    let mut blocks: Vec<(usize, Vec<&str>)> = vec![(1, Vec::new())];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim_end() == DIVIDER {
+            blocks.push((i + 2, Vec::new()));
+        } else {
+            blocks.last_mut().unwrap().1.push(line);
+        }
+    }
+
+    // A trailing divider doesn't start another block
+    if blocks.len() > 1 && blocks.last().unwrap().1.iter().all(|l| l.trim().is_empty()) {
+        blocks.pop();
+    }
+
+    let mut records = Vec::with_capacity(blocks.len() / 2);
+    let mut errors = Vec::new();
+
+    for pair in blocks.chunks(2) {
+        let (line, front) = &pair[0];
+        let Some((_, back)) = pair.get(1) else {
+            if front.iter().any(|l| !l.trim().is_empty()) {
+                errors.push(LineError {
+                    line: *line,
+                    message: format!("the front of the card has no {DIVIDER} and back after it"),
+                });
+            }
+            continue;
+        };
+
+        let mut back = back.as_slice();
+        while back.last().is_some_and(|l| l.trim().is_empty()) {
+            back = &back[..back.len() - 1];
+        }
+
+        let tags = match back.last().and_then(|l| l.strip_prefix(TAGS_PREFIX)) {
+            Some(tags) => {
+                back = &back[..back.len() - 1];
+                tags::parse_list(tags)
+            }
+            None => Ok(Vec::new()),
+        };
+
+        match tags {
+            Ok(tags) => records.push(Record {
+                line: *line,
+                front: front.join("\n").trim().to_string(),
+                back: back.join("\n").trim().to_string(),
+                tags,
+            }),
+            Err(e) => errors.push(LineError {
+                line: *line,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    (records, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: usize, front: &str, back: &str, tags: &[&str]) -> Record {
+        Record {
+            line,
+            front: front.to_string(),
+            back: back.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn csv_supports_quotes_and_multiple_lines() {
+        let text = "front,back,tags\r\n\
+                    a, b \r\n\
+                    \"c, \"\"d\"\"\",\"e\nf\",x y\n\
+                    \n\
+                    g,h\n";
+
+        let (records, errors) = parse(text, Format::Csv);
+        assert_eq!(errors, []);
+        assert_eq!(
+            records,
+            [
+                record(2, "a", "b", &[]),
+                record(3, "c, \"d\"", "e\nf", &["x", "y"]),
+                record(6, "g", "h", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn delimited_errors_are_reported_per_line() {
+        let text = "a\tb\n\
+                    only one\n\
+                    \"x\"y\tz\n\
+                    a\tb\tc\td\n\
+                    \"never\n\
+                    closed\tb\n";
+
+        let (records, errors) = parse(text, Format::Tsv);
+        assert_eq!(records, [record(1, "a", "b", &[])]);
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            [2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn anki_headers_and_html_are_read() {
+        let text = "#separator:Pipe\n\
+                    #html:true\n\
+                    #deck column:1\n\
+                    #tags column:5\n\
+                    deck|a<br>b|<div>x</div><div><b>y</b>&nbsp;&amp;&#x41;</div>|extra|Tag1 tag2\n\
+                    deck|\"c|d\"|&lt;e&gt; & f\n\
+                    deck|only\n";
+
+        let (records, errors) = parse(text, Format::Anki);
+        assert_eq!(
+            records,
+            [
+                record(5, "a\nb", "x\ny &A", &["tag1", "tag2"]),
+                record(6, "c|d", "<e> & f", &[]),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 7);
+
+        assert_eq!(html_to_text("&日本語日本語; 1 <2"), "&日本語日本語; 1 <2");
+    }
+
+    #[test]
+    fn anki_exports_are_detected() {
+        let path = Path::new("notes.txt");
+
+        assert_eq!(
+            Format::detect(path, "#separator:tab\n#html:false\na\tb\n"),
+            Format::Anki
+        );
+        assert_eq!(Format::detect(path, "# a heading\n"), Format::Text);
+        assert_eq!(
+            Format::detect(Path::new("notes.csv"), "#html:true\n"),
+            Format::Csv
+        );
+    }
+
+    #[test]
+    fn text_blocks_alternate_between_front_and_back() {
+        let text = "a\n\
+                    ----------\n\
+                    b\n\
+                    c\n\
+                    \n\
+                    tags: x\n\
+                    ----------\n\
+                    d\n\
+                    ----------\n\
+                    e\n\
+                    ----------\n\
+                    f\n";
+
+        let (records, errors) = parse(text, Format::Text);
+        assert_eq!(
+            records,
+            [record(1, "a", "b\nc", &["x"]), record(8, "d", "e", &[])]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 12);
+    }
+
+    #[test]
+    fn new_cards_are_checked() {
+        let srs = Srs::default();
+        let text = "a\n----------\nb\n----------\n\n----------\nc\n----------\nd";
+
+        let (records, errors) = parse_new_cards(&srs, text).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            errors,
+            [
+                LineError {
+                    line: 5,
+                    message: "the front of the card is empty".to_string(),
+                },
+                LineError {
+                    line: 9,
+                    message: format!("the front of the card has no {DIVIDER} and back after it"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_new_cards_is_an_error() {
+        let srs = Srs::default();
+
+        for text in ["", "\n\n", "----------\n"] {
+            let (records, errors) = parse_new_cards(&srs, text).unwrap();
+            assert_eq!(records, [], "{text:?}");
+            assert_eq!(errors.len(), 1, "{text:?}");
+            assert_eq!(errors[0].line, 1, "{text:?}");
+        }
+    }
+}
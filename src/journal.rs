@@ -0,0 +1,377 @@
+//! A record of the answers given during a review session. Answers are only applied to the
+//! database at the end of a session, so the journal keeps them from being lost when the session
+//! is interrupted.
+//!
+//! The journal is kept next to the database at `<path>.journal`, with one line per answer
+//! containing the day it was given on, the card ID, and the grade. The session holds a lock on
+//! it, so that other processes can tell whether it was left behind by an interrupted session, or
+//! is still being written to.
+//!
+//! Answers are applied a day at a time. Before each day's answers are written to the database, a
+//! line containing the database's generation and the number of answers applied by the end of the
+//! write is added to the journal. If the process stops after the write but before the journal is
+//! deleted, the database's generation will have changed, so those answers aren't applied again.
+
+use crate::error::Result;
+use crate::rand::Rng;
+use crate::{Answer, Grade, Srs, apply_answers, open};
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub struct Journal {
+    file: File,
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Opens the journal for the database at the given path, creating it if it doesn't exist.
+    /// Fails if another review session of the database is in progress.
+    ///
+    /// Answers left by an interrupted session are kept, but they should be applied with [apply]
+    /// first so that they aren't applied again with the answers of this session.
+    pub fn create(db_path: &Path) -> Result<Self> {
+        let path = path(db_path);
+        let file = File::options()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| format!("failed to create {}: {e}", path.to_string_lossy()))?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Self { file, path }),
+            Err(TryLockError::WouldBlock) => {
+                Err("another review of this database is in progress".into())
+            }
+            Err(TryLockError::Error(e)) => {
+                Err(format!("failed to lock {}: {e}", path.to_string_lossy()).into())
+            }
+        }
+    }
+
+    /// Records an answer given on the given day, and waits until it's on disk.
+    pub fn append(&mut self, day: u16, answer: &Answer) -> Result<()> {
+        writeln!(self.file, "{day} {} {}", answer.card_id, answer.grade as u8)?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Applies the answers in the journal to the database at the given path, and deletes the
+    /// journal. Returns the number of answers which were applied.
+    pub fn apply(mut self, db_path: &Path) -> Result<usize> {
+        let (_, count) = apply_entries(&mut self.file, &self.path, db_path)?;
+        remove_path(&self.path)?;
+
+        Ok(count)
+    }
+}
+
+/// The lines of a journal.
+struct Contents {
+    entries: Vec<(u16, Answer)>,
+    /// The generation of the database, and the number of entries applied once it's written, for
+    /// each write of the entries to the database which was started.
+    writes: Vec<(u32, usize)>,
+    /// The length of the journal without a cut-off last line.
+    len: usize,
+}
+
+impl Contents {
+    /// Returns the number of entries which have already been written to the database, given its
+    /// current generation.
+    fn num_applied(&self, generation: u32) -> usize {
+        match self.writes.as_slice() {
+            [.., (g, count)] if *g != generation => *count,
+            // The last write didn't happen, but each one is only started once the previous one
+            // is done.
+            [.., (_, count), _] => *count,
+            _ => 0,
+        }
+    }
+}
+
+/// Returns the answers in the journal of the database at the given path, along with the day each
+/// one was given on. The journal only exists when a review session was interrupted before its
+/// answers were applied, or is still in progress.
+pub fn read(db_path: &Path) -> Result<Vec<(u16, Answer)>> {
+    let path = path(db_path);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(parse(&contents, &path)?.entries),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("failed to read {}: {e}", path.to_string_lossy()).into()),
+    }
+}
+
+/// Applies the answers left in the journal by an interrupted review session to the database at
+/// the given path, and deletes the journal. Returns the updated database, and the number of
+/// answers which were applied. Answers which were already written to the database before the
+/// session was interrupted aren't applied again.
+///
+/// Nothing is applied while a review session is still writing to the journal, since it applies
+/// the answers itself when it ends.
+pub fn apply(srs: Srs, db_path: &Path) -> Result<(Srs, usize)> {
+    let path = path(db_path);
+    let mut file = match File::options().read(true).append(true).open(&path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((srs, 0)),
+        Err(e) => return Err(format!("failed to open {}: {e}", path.to_string_lossy()).into()),
+    };
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok((srs, 0)),
+        Err(TryLockError::Error(e)) => {
+            return Err(format!("failed to lock {}: {e}", path.to_string_lossy()).into());
+        }
+    }
+
+    let result = apply_entries(&mut file, &path, db_path)?;
+    remove_path(&path)?;
+
+    Ok(result)
+}
+
+/// Writes the entries of the journal which haven't been applied yet to the database, and returns
+/// the updated database along with the number of entries which were written.
+fn apply_entries(file: &mut File, path: &Path, db_path: &Path) -> Result<(Srs, usize)> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| format!("failed to read {}: {e}", path.to_string_lossy()))?;
+    let contents = parse(&contents, path)?;
+
+    // Drop a cut-off last line, so that the writes are recorded on lines of their own.
+    file.set_len(contents.len as u64)?;
+
+    // The database is read again, rather than using the version a review session started with, so
+    // that the recorded generations are the ones being written over.
+    let mut srs = open(db_path)?;
+    let already_applied = contents.num_applied(srs.generation);
+    let mut applied = already_applied;
+
+    let mut rng = Rng::default();
+    for entries_for_day in contents.entries[already_applied..].chunk_by(|(a, _), (b, _)| a == b) {
+        let day = entries_for_day[0].0;
+        let answers: Vec<Answer> = entries_for_day.iter().map(|(_, answer)| *answer).collect();
+
+        applied += answers.len();
+        writeln!(file, "applying {} {applied}", srs.generation)?;
+        file.sync_data()?;
+
+        apply_answers(srs, db_path, day, &answers, &mut rng)?;
+        srs = open(db_path)?;
+    }
+
+    Ok((srs, applied - already_applied))
+}
+
+fn parse(contents: &str, path: &Path) -> Result<Contents> {
+    // A line without a newline at the end was cut off part way through being written, so the
+    // answer wasn't recorded.
+    let len = contents.rfind('\n').map_or(0, |end| end + 1);
+
+    let mut entries = Vec::new();
+    let mut writes = Vec::new();
+    for (i, line) in contents[..len].lines().enumerate() {
+        if let Some(write) = line.strip_prefix("applying ").and_then(parse_write) {
+            writes.push(write);
+        } else if let Some(entry) = parse_line(line) {
+            entries.push(entry);
+        } else {
+            return Err(format!(
+                "invalid entry on line {} of {}: {line:?}",
+                i + 1,
+                path.to_string_lossy(),
+            )
+            .into());
+        }
+    }
+
+    Ok(Contents {
+        entries,
+        writes,
+        len,
+    })
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(format!("failed to delete {}: {e}", path.to_string_lossy()).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u16, Answer)> {
+    let mut fields = line.split(' ');
+
+    let day = fields.next()?.parse().ok()?;
+    let card_id = fields.next()?.parse().ok()?;
+    let grade = Grade::from_number(fields.next()?.parse().ok()?)?;
+
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some((day, Answer { card_id, grade }))
+}
+
+fn parse_write(fields: &str) -> Option<(u32, usize)> {
+    let (generation, count) = fields.split_once(' ')?;
+
+    Some((generation.parse().ok()?, count.parse().ok()?))
+}
+
+fn path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".journal");
+
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{TempDir, add};
+
+    fn answer(card_id: u32, grade: Grade) -> Answer {
+        Answer { card_id, grade }
+    }
+
+    #[test]
+    fn a_cut_off_last_line_is_ignored() {
+        let dir = TempDir::new();
+        let db_path = dir.db();
+        fs::write(path(&db_path), "20000 1 3\n20001 2 1\n20001 3").unwrap();
+
+        let entries: Vec<_> = read(&db_path)
+            .unwrap()
+            .into_iter()
+            .map(|(day, a)| (day, a.card_id, a.grade))
+            .collect();
+
+        assert_eq!(
+            entries,
+            [(20_000, 1, Grade::Good), (20_001, 2, Grade::Again)]
+        );
+    }
+
+    #[test]
+    fn invalid_lines_are_errors() {
+        let dir = TempDir::new();
+        let db_path = dir.db();
+
+        for contents in ["20000 1 5\n", "20000 1\n", "20000 1 3 4\n", "x 1 3\n"] {
+            fs::write(path(&db_path), contents).unwrap();
+            assert!(read(&db_path).is_err(), "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn a_missing_journal_has_no_answers() {
+        let dir = TempDir::new();
+
+        assert!(read(&dir.db()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn applying_saves_the_answers_and_removes_the_journal() {
+        let dir = TempDir::new();
+        let db_path = dir.db();
+        add(&db_path, "a");
+        add(&db_path, "b");
+
+        let mut journal = Journal::create(&db_path).unwrap();
+        journal.append(20_000, &answer(0, Grade::Good)).unwrap();
+        journal.append(20_001, &answer(1, Grade::Again)).unwrap();
+        drop(journal);
+
+        let (srs, count) = apply(open(&db_path).unwrap(), &db_path).unwrap();
+
+        assert_eq!(count, 2);
+        let history: Vec<_> = srs.history.iter().map(|r| (r.day, r.card_id)).collect();
+        assert_eq!(history, [(20_000, 0), (20_001, 1)]);
+        assert_eq!(srs.schedule[1].scheduled_for, 0);
+        assert!(!path(&db_path).exists());
+        assert_eq!(open(&db_path).unwrap().history.len(), 2);
+    }
+
+    #[test]
+    fn answers_already_written_to_the_database_arent_applied_again() {
+        let dir = TempDir::new();
+        let db_path = dir.db();
+        add(&db_path, "a");
+        add(&db_path, "b");
+        let generation = open(&db_path).unwrap().generation;
+
+        // Stops after writing the first day's answers.
+        fs::write(path(&db_path), "20000 0 3\n").unwrap();
+        apply(open(&db_path).unwrap(), &db_path).unwrap();
+        fs::write(
+            path(&db_path),
+            format!("20000 0 3\n20001 1 1\napplying {generation} 1\n"),
+        )
+        .unwrap();
+
+        let (srs, count) = apply(open(&db_path).unwrap(), &db_path).unwrap();
+        assert_eq!(count, 1);
+        let history: Vec<_> = srs.history.iter().map(|r| (r.day, r.card_id)).collect();
+        assert_eq!(history, [(20_000, 0), (20_001, 1)]);
+
+        // Stops after writing every answer, before deleting the journal.
+        fs::write(
+            path(&db_path),
+            format!(
+                "20000 0 3\n20001 1 1\napplying {generation} 1\napplying {} 2\n",
+                generation + 1,
+            ),
+        )
+        .unwrap();
+
+        let (srs, count) = apply(open(&db_path).unwrap(), &db_path).unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(srs.history.len(), 2);
+        assert!(!path(&db_path).exists());
+    }
+
+    #[test]
+    fn answers_are_applied_if_the_database_wasnt_written() {
+        let dir = TempDir::new();
+        let db_path = dir.db();
+        add(&db_path, "a");
+        let generation = open(&db_path).unwrap().generation;
+        fs::write(
+            path(&db_path),
+            format!("20000 0 3\napplying {generation} 1\n20001 0"),
+        )
+        .unwrap();
+
+        let (srs, count) = apply(open(&db_path).unwrap(), &db_path).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(srs.history.len(), 1);
+    }
+
+    #[test]
+    fn journals_of_reviews_in_progress_are_left_alone() {
+        let dir = TempDir::new();
+        let db_path = dir.db();
+        add(&db_path, "a");
+
+        let mut journal = Journal::create(&db_path).unwrap();
+        journal.append(20_000, &answer(0, Grade::Good)).unwrap();
+
+        let (srs, count) = apply(open(&db_path).unwrap(), &db_path).unwrap();
+        assert_eq!(count, 0);
+        assert!(srs.history.is_empty());
+
+        assert!(Journal::create(&db_path).is_err());
+
+        drop(journal);
+        let mut journal = Journal::create(&db_path).unwrap();
+        journal.append(20_001, &answer(0, Grade::Easy)).unwrap();
+        assert_eq!(read(&db_path).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,451 @@
+//! Just enough JSON to write exports and other output, and to read exports back.
+
+use crate::error::Result;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// The number as written, so that it can be parsed into the type it's needed as without
+    /// losing precision.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// The members of the object, in the order they were written.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn number(n: impl fmt::Display) -> Self {
+        Self::Number(n.to_string())
+    }
+
+    pub fn string(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+
+    pub fn object(members: impl IntoIterator<Item = (&'static str, Value)>) -> Self {
+        Self::Object(
+            members
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Returns the member of an object with the given name.
+    pub fn get(&self, name: &str) -> Result<&Value> {
+        match self {
+            Self::Object(members) => members
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v)
+                .ok_or_else(|| format!("missing '{name}'").into()),
+            _ => Err(format!("expected an object containing '{name}'").into()),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Value]> {
+        match self {
+            Self::Array(values) => Ok(values),
+            _ => Err("expected an array".into()),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        match self {
+            Self::String(s) => Ok(s),
+            _ => Err("expected a string".into()),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match self {
+            Self::Bool(b) => Ok(*b),
+            _ => Err("expected true or false".into()),
+        }
+    }
+
+    pub fn as_number<T>(&self) -> Result<T>
+    where
+        T: FromStr,
+    {
+        match self {
+            Self::Number(n) => n
+                .parse()
+                .map_err(|_| format!("number {n} is out of range").into()),
+            _ => Err("expected a number".into()),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Self::Null
+    }
+}
+
+/// Returns the given string as a JSON string, with quotes.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Formats a value with each member of an object and element of an array on its own line,
+/// indented by two spaces. Arrays and objects which only contain strings, numbers, booleans and
+/// nulls are kept on one line.
+pub fn to_string_pretty(value: &Value) -> String {
+    let mut out = String::new();
+    write_pretty(&mut out, value, 0);
+    out
+}
+
+fn write_pretty(out: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{b}").unwrap(),
+        Value::Number(n) => out.push_str(n),
+        Value::String(s) => out.push_str(&quote(s)),
+        Value::Array(values) if values.is_empty() => out.push_str("[]"),
+        Value::Array(values) if is_flat(values.iter()) => {
+            out.push('[');
+            for (i, v) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_pretty(out, v, depth);
+            }
+            out.push(']');
+        }
+        Value::Array(values) => {
+            out.push('[');
+            for (i, v) in values.iter().enumerate() {
+                out.push_str(if i > 0 { ",\n" } else { "\n" });
+                indent(out, depth + 1);
+                write_pretty(out, v, depth + 1);
+            }
+            out.push('\n');
+            indent(out, depth);
+            out.push(']');
+        }
+        Value::Object(members) if members.is_empty() => out.push_str("{}"),
+        Value::Object(members) if is_flat(members.iter().map(|(_, v)| v)) => {
+            out.push_str("{ ");
+            for (i, (name, v)) in members.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write!(out, "{}: ", quote(name)).unwrap();
+                write_pretty(out, v, depth);
+            }
+            out.push_str(" }");
+        }
+        Value::Object(members) => {
+            out.push('{');
+            for (i, (name, v)) in members.iter().enumerate() {
+                out.push_str(if i > 0 { ",\n" } else { "\n" });
+                indent(out, depth + 1);
+                write!(out, "{}: ", quote(name)).unwrap();
+                write_pretty(out, v, depth + 1);
+            }
+            out.push('\n');
+            indent(out, depth);
+            out.push('}');
+        }
+    }
+}
+
+/// Returns whether none of the values are arrays or objects.
+fn is_flat<'a>(mut values: impl Iterator<Item = &'a Value>) -> bool {
+    values.all(|v| !matches!(v, Value::Array(_) | Value::Object(_)))
+}
+
+fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: text.char_indices().collect(),
+        pos: 0,
+        len: text.len(),
+    };
+
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected text after the end of the JSON"));
+    }
+
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    /// The length of the text in bytes.
+    len: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> Box<dyn std::error::Error> {
+        let offset = self.chars.get(self.pos).map_or(self.len, |&(i, _)| i);
+        format!("{message} at byte {offset}").into()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|&(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{expected}'")));
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                self.pos -= 1;
+                return Err(self.error(&format!("expected '{word}'")));
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of JSON")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            self.expect(':')?;
+            members.push((name, self.value()?));
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(members)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected ',' or '}'"));
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected ',' or ']'"));
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+
+        let number: String = self.chars[start..self.pos]
+            .iter()
+            .map(|&(_, c)| c)
+            .collect();
+        if number.parse::<f64>().is_err() {
+            self.pos = start;
+            return Err(self.error("invalid number"));
+        }
+
+        Ok(Value::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let high = self.hex_escape()?;
+                        let code = if (0xD800..0xDC00).contains(&high) {
+                            // A surrogate pair
+                            if self.next() != Some('\\') || self.next() != Some('u') {
+                                return Err(self.error("unpaired surrogate"));
+                            }
+                            let low = self.hex_escape()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err(self.error("unpaired surrogate"));
+                            }
+                            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                        } else {
+                            high
+                        };
+
+                        s.push(char::from_u32(code).ok_or_else(|| self.error("invalid escape"))?);
+                    }
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("invalid escape"));
+                    }
+                },
+                Some(c) if c.is_control() => {
+                    self.pos -= 1;
+                    return Err(self.error("unescaped control character in string"));
+                }
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid \\u escape"))?;
+            code = code * 16 + digit;
+        }
+
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_strings_parse_back() {
+        let s = "a \"quote\", a \\ backslash\nand a \u{1} control character: 日本語";
+        assert_eq!(parse(&quote(s)).unwrap(), Value::String(s.to_string()));
+        assert_eq!(parse(r#""é😀""#).unwrap(), Value::String("é😀".to_string()));
+    }
+
+    #[test]
+    fn parses_nested_values() {
+        let value = parse(r#" {"a": [1, -2.5e3, true, null], "b": {}} "#).unwrap();
+
+        let a = value.get("a").unwrap().as_array().unwrap();
+        assert_eq!(a[0].as_number::<u8>().unwrap(), 1);
+        assert_eq!(a[1].as_number::<f32>().unwrap(), -2500.0);
+        assert_eq!(a[2], Value::Bool(true));
+        assert!(a[3].is_null());
+        assert_eq!(*value.get("b").unwrap(), Value::Object(Vec::new()));
+        assert!(value.get("c").is_err());
+    }
+
+    #[test]
+    fn pretty_output_parses_back() {
+        let value = parse(r#"{"a": [1, {"b": "c", "d": false}], "e": {"f": null}}"#).unwrap();
+        let pretty = to_string_pretty(&value);
+
+        assert_eq!(
+            pretty,
+            "{\n  \"a\": [\n    1,\n    { \"b\": \"c\", \"d\": false }\n  ],\n  \"e\": { \"f\": null }\n}"
+        );
+        assert_eq!(parse(&pretty).unwrap(), value);
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        for json in ["", "[1,]", "{\"a\" 1}", "\"open", "[1] 2", "tru", "{1: 2}"] {
+            assert!(parse(json).is_err(), "{json}");
+        }
+    }
+}
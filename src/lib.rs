@@ -1,21 +1,38 @@
+mod backup;
+pub mod date;
+pub mod dedupe;
 pub mod editor;
 pub mod error;
+pub mod export;
+mod format;
+mod fuzz;
+pub mod import;
+pub mod journal;
+pub mod json;
+pub mod limits;
+mod lock;
 pub mod prompt;
+pub mod queue;
 pub mod rand;
+mod regex;
+pub mod scheduler;
+pub mod search;
+pub mod tags;
 mod tmp;
+pub mod vacation;
 
-use error::Result;
+use error::{Conflict, Result};
 use rand::Rng;
+use scheduler::{Memory, SchedulerKind};
 use std::{
+    collections::{HashMap, HashSet},
+    fmt,
     fs::{self, File},
-    io::{BufWriter, Write},
-    path::Path,
+    io::BufWriter,
+    path::{Path, PathBuf},
     str,
 };
 
-/// The reduction factor applied to the next interval when the card was answered incorrectly.
-const WRONG_ANSWER_PENALTY: f32 = 0.7;
-
 const MAX_CARD_COUNT: usize = u16::MAX as usize;
 // The file format can handle longer cards, but this should be more than enough.
 const MAX_CARD_LEN: usize = 4 * 1024;
@@ -23,7 +40,32 @@ const MAX_CARD_LEN: usize = 4 * 1024;
 pub struct Srs {
     pub cards: Box<[Vec<u8>]>,
     pub schedule: Box<[CardSchedule]>,
-    pub stats: Box<Stats>,
+    /// The persistent ID of each card. Unlike a [CardIndex], the ID of a card doesn't change when
+    /// other cards are deleted.
+    pub ids: Box<[CardId]>,
+    /// The ID to assign to the next card that's added. IDs are never reused.
+    pub next_id: CardId,
+    /// Every answer given during reviews, in the order they were given.
+    pub history: Box<[Review]>,
+    /// Counts of answers given before [Srs::history] was kept. Use [stats] to get counts which
+    /// include all answers.
+    pub legacy_stats: Box<Stats>,
+    pub settings: Settings,
+    /// The deck that each card is in.
+    pub card_decks: Box<[DeckId]>,
+    /// Every deck, including the [DEFAULT_DECK].
+    pub decks: Box<[Deck]>,
+    /// The tags of each card. See [tags] for what they can contain.
+    pub card_tags: Box<[Vec<String>]>,
+    /// The day vacation mode was turned on, when it's on. No cards are due while it is, and due
+    /// dates are pushed back by the length of the vacation when it ends.
+    pub vacation_start: Option<u16>,
+    /// The total number of days due dates have been pushed back by, with [vacation::shift] or
+    /// vacations. [check] allows for it when looking for cards scheduled too far ahead.
+    pub shifted_days: u16,
+    /// The number of times the database has been written. Used to detect changes made by other
+    /// processes.
+    pub generation: u32,
 }
 
 impl Default for Srs {
@@ -31,7 +73,21 @@ impl Default for Srs {
         Self {
             cards: Box::new([]),
             schedule: Box::new([]),
-            stats: Box::new([Stat::default(); STAT_ROW_COUNT]),
+            ids: Box::new([]),
+            next_id: 0,
+            history: Box::new([]),
+            legacy_stats: Box::new([Stat::default(); STAT_ROW_COUNT]),
+            settings: Settings::default(),
+            card_decks: Box::new([]),
+            decks: Box::new([Deck {
+                id: DEFAULT_DECK,
+                name: "default".to_string(),
+                settings: None,
+            }]),
+            card_tags: Box::new([]),
+            vacation_start: None,
+            shifted_days: 0,
+            generation: 0,
         }
     }
 }
@@ -40,6 +96,26 @@ impl Default for Srs {
 pub struct CardSchedule {
     pub most_recent_interval: u16,
     pub scheduled_for: u16,
+    pub memory: Memory,
+}
+
+/// Options which apply to the whole database, unless a deck has its own.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Settings {
+    /// The algorithm used to schedule cards when they're answered.
+    pub scheduler: SchedulerKind,
+    /// The most cards that have been answered before to review each day. None for no limit.
+    pub review_limit: Option<u16>,
+    /// The most cards that have never been answered to introduce each day. None for no limit.
+    pub new_limit: Option<u16>,
+    /// Which reviews are kept for the day when there are more than [Settings::review_limit].
+    pub overflow_order: limits::OverflowOrder,
+    /// Whether answered cards are scheduled for the day in the fuzz window with the fewest cards
+    /// already due, instead of a random day in it.
+    pub load_balance: bool,
+    /// The longest interval that cards are given, in days. None for no maximum, other than the
+    /// last day that can be stored.
+    pub max_interval: Option<u16>,
 }
 
 const STAT_ROW_COUNT: usize = 365;
@@ -48,12 +124,83 @@ pub type Stats = [Stat; STAT_ROW_COUNT];
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Stat {
-    pub correct: u8,
-    pub wrong: u8,
+    pub correct: u32,
+    pub wrong: u32,
 }
 
+/// An entry in the review history, recording one answer to a card.
+#[derive(Clone, Copy, Debug)]
+pub struct Review {
+    pub card_id: CardId,
+    /// The day the answer was given on, in days since the epoch.
+    pub day: u16,
+    /// The card's interval before the answer.
+    pub previous_interval: u16,
+    /// The card's interval after the answer. It's the same as the previous interval for wrong
+    /// answers.
+    pub new_interval: u16,
+    pub grade: Grade,
+}
+
+/// The position of a card in [Srs::cards]. It changes when a card before it is deleted.
 pub type CardIndex = u16;
 
+/// The persistent identifier of a card, which users refer to it by.
+pub type CardId = u32;
+
+/// The persistent identifier of a deck.
+pub type DeckId = u16;
+
+/// The deck that cards are added to when no other deck is given. It can't be deleted.
+pub const DEFAULT_DECK: DeckId = 0;
+
+// Deck names are stored with a u8 length.
+const MAX_DECK_NAME_LEN: usize = u8::MAX as usize;
+
+/// A named group of cards, which can be reviewed separately from the others.
+#[derive(Clone, Debug)]
+pub struct Deck {
+    pub id: DeckId,
+    pub name: String,
+    /// Settings used for cards in this deck instead of [Srs::settings]. None when the deck uses
+    /// the settings of the database.
+    pub settings: Option<Settings>,
+}
+
+/// Restricts which cards a subcommand applies to. The default matches every card.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Only include cards in this deck.
+    pub deck: Option<DeckId>,
+    /// Only include cards whose tags match this expression.
+    pub tags: Option<tags::Expr>,
+}
+
+impl Filter {
+    /// Returns whether every card matches.
+    pub fn is_empty(&self) -> bool {
+        self.deck.is_none() && self.tags.is_none()
+    }
+
+    pub fn matches(&self, srs: &Srs, i: CardIndex) -> bool {
+        let i = usize::from(i);
+
+        self.deck.is_none_or(|deck| srs.card_decks[i] == deck)
+            && self
+                .tags
+                .as_ref()
+                .is_none_or(|expr| expr.matches(&srs.card_tags[i]))
+    }
+}
+
+/// Something wrong with the contents of a database file, found by [check].
+#[derive(Debug)]
+pub struct Problem {
+    /// The position of the problem, in bytes from the start of the file.
+    pub offset: usize,
+    pub description: String,
+}
+
 #[derive(Debug)]
 pub struct Card {
     pub front: Box<str>,
@@ -61,54 +208,156 @@ pub struct Card {
 }
 
 impl Card {
-    const SEPARATOR: u8 = b'\0';
+    /// The byte stored between the front and back of a card.
+    pub const SEPARATOR: u8 = b'\0';
     const SEPARATOR_STR: &[u8; 1] = b"\0";
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Answer {
-    pub card_index: CardIndex,
-    pub is_correct: bool,
+    pub card_id: CardId,
+    pub grade: Grade,
+}
+
+/// How well a card was remembered when it was answered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grade {
+    /// The card was forgotten.
+    Again = 1,
+    /// The card was remembered, but with difficulty.
+    Hard = 2,
+    Good = 3,
+    /// The card was remembered without any effort.
+    Easy = 4,
+}
+
+impl Grade {
+    pub const ALL: [Self; 4] = [Self::Again, Self::Hard, Self::Good, Self::Easy];
+
+    pub fn is_correct(self) -> bool {
+        self != Self::Again
+    }
+
+    /// Returns the grade with the given number, from 1 to 4.
+    pub fn from_number(n: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| *g as u8 == n)
+    }
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Again => "Again",
+            Self::Hard => "Hard",
+            Self::Good => "Good",
+            Self::Easy => "Easy",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// A card to add with [add_cards].
+#[derive(Clone, Debug)]
+pub struct NewCard {
+    pub front: String,
+    pub back: String,
+    pub deck: DeckId,
+    pub tags: Vec<String>,
 }
 
+/// Adds a card to the given deck, and returns its ID.
 pub fn add_card(
     srs: Srs,
     path: &Path,
     now_in_epoch_days: u16,
+    deck: DeckId,
     front: String,
     back: String,
-) -> Result<()> {
-    if srs.cards.len() >= MAX_CARD_COUNT {
-        return Err("reached card count limit".into());
-    }
+    tags: Vec<String>,
+) -> Result<CardId> {
+    let card = NewCard {
+        front,
+        back,
+        deck,
+        tags,
+    };
+
+    Ok(add_cards(srs, path, now_in_epoch_days, &[card])?[0])
+}
 
-    let card: Vec<u8> = front
-        .as_bytes()
+/// Adds the given cards in a single write, and returns their IDs. Either all of them are added,
+/// or none are.
+pub fn add_cards(
+    srs: Srs,
+    path: &Path,
+    now_in_epoch_days: u16,
+    new_cards: &[NewCard],
+) -> Result<Vec<CardId>> {
+    let encoded = new_cards
         .iter()
-        .chain(Card::SEPARATOR_STR)
-        .chain(back.as_bytes())
-        .copied()
-        .collect();
+        .map(|c| encode_card(&c.front, &c.back))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut new_ids = Vec::with_capacity(new_cards.len());
+    update(srs, path, |srs| {
+        check_card_count(&srs, new_cards.len())?;
+        for card in new_cards {
+            deck_index(&srs, card.deck)?;
+        }
 
-    if card.len() > MAX_CARD_LEN {
-        return Err("this card is too long".into());
-    }
+        let mut cards = srs.cards.into_vec();
+        let mut schedule = srs.schedule.into_vec();
+        let mut ids = srs.ids.into_vec();
+        let mut card_decks = srs.card_decks.into_vec();
+        let mut card_tags = srs.card_tags.into_vec();
+
+        new_ids.clear();
+        let mut next_id = srs.next_id;
+        for (card, bytes) in new_cards.iter().zip(&encoded) {
+            cards.push(bytes.clone());
+            schedule.push(CardSchedule {
+                most_recent_interval: 1,
+                scheduled_for: now_in_epoch_days.saturating_add(1),
+                memory: Memory::default(),
+            });
+            ids.push(next_id);
+            card_decks.push(card.deck);
+            card_tags.push(card.tags.clone());
+
+            new_ids.push(next_id);
+            next_id = next_id.checked_add(1).ok_or("ran out of card IDs")?;
+        }
 
-    let mut cards = srs.cards.into_vec();
-    cards.push(card);
+        Ok(Srs {
+            cards: cards.into_boxed_slice(),
+            schedule: schedule.into_boxed_slice(),
+            ids: ids.into_boxed_slice(),
+            card_decks: card_decks.into_boxed_slice(),
+            card_tags: card_tags.into_boxed_slice(),
+            next_id,
+            ..srs
+        })
+    })?;
 
-    let mut schedule = srs.schedule.into_vec();
-    schedule.push(CardSchedule {
-        most_recent_interval: 1,
-        scheduled_for: now_in_epoch_days + 1,
-    });
+    Ok(new_ids)
+}
 
-    write(path, cards, &schedule, *srs.stats)?;
+/// Returns an error if adding `count` cards would go over the limit on the number of cards.
+pub fn check_card_count(srs: &Srs, count: usize) -> Result<()> {
+    if srs.cards.len() + count > MAX_CARD_COUNT {
+        return Err(format!(
+            "reached card count limit: there's only room for {} more cards",
+            MAX_CARD_COUNT - srs.cards.len(),
+        )
+        .into());
+    }
 
     Ok(())
 }
 
-pub fn edit_card(srs: Srs, path: &Path, idx: CardIndex, front: String, back: String) -> Result<()> {
+/// Returns the bytes that a card with the given front and back is stored as.
+pub fn encode_card(front: &str, back: &str) -> Result<Vec<u8>> {
     let card: Vec<u8> = front
         .as_bytes()
         .iter()
@@ -118,27 +367,81 @@ pub fn edit_card(srs: Srs, path: &Path, idx: CardIndex, front: String, back: Str
         .collect();
 
     if card.len() > MAX_CARD_LEN {
-        return Err("this card is too long".into());
+        return Err(format!(
+            "this card is too long: {} bytes, but the limit is {MAX_CARD_LEN}",
+            card.len()
+        )
+        .into());
     }
 
-    let mut cards = srs.cards.into_vec();
-    cards[usize::from(idx)] = card;
+    Ok(card)
+}
 
-    write(path, cards, &srs.schedule, *srs.stats)?;
+pub fn edit_card(
+    srs: Srs,
+    path: &Path,
+    id: CardId,
+    front: String,
+    back: String,
+    tags: Vec<String>,
+) -> Result<()> {
+    let card = encode_card(&front, &back)?;
 
-    Ok(())
+    update(srs, path, |srs| {
+        let idx = usize::from(card_index(&srs, id)?);
+
+        let mut cards = srs.cards.into_vec();
+        cards[idx] = card.clone();
+
+        let mut card_tags = srs.card_tags.into_vec();
+        card_tags[idx] = tags.clone();
+
+        Ok(Srs {
+            cards: cards.into_boxed_slice(),
+            card_tags: card_tags.into_boxed_slice(),
+            ..srs
+        })
+    })
 }
 
-pub fn delete_card(srs: Srs, path: &Path, idx: CardIndex) -> Result<()> {
-    let mut cards = srs.cards.into_vec();
-    cards.remove(usize::from(idx));
+pub fn delete_card(srs: Srs, path: &Path, id: CardId) -> Result<()> {
+    update(srs, path, |srs| {
+        let idx = usize::from(card_index(&srs, id)?);
+
+        Ok(retain_cards(srs, |i| i != idx))
+    })
+}
 
-    let mut schedule = srs.schedule.into_vec();
-    schedule.remove(usize::from(idx));
+/// Removes the cards whose index `keep` returns false for.
+fn retain_cards(srs: Srs, mut keep: impl FnMut(usize) -> bool) -> Srs {
+    let keep: Vec<bool> = (0..srs.cards.len()).map(&mut keep).collect();
+
+    fn retain<T>(items: Box<[T]>, keep: &[bool]) -> Box<[T]> {
+        items
+            .into_vec()
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(item, &keep)| keep.then_some(item))
+            .collect()
+    }
 
-    write(path, cards, &schedule, *srs.stats)?;
+    Srs {
+        cards: retain(srs.cards, &keep),
+        schedule: retain(srs.schedule, &keep),
+        ids: retain(srs.ids, &keep),
+        card_decks: retain(srs.card_decks, &keep),
+        card_tags: retain(srs.card_tags, &keep),
+        ..srs
+    }
+}
 
-    Ok(())
+/// Returns the current position of the card with the given ID.
+pub fn card_index(srs: &Srs, id: CardId) -> Result<CardIndex> {
+    srs.ids
+        .iter()
+        .position(|&i| i == id)
+        .map(|i| i as CardIndex)
+        .ok_or_else(|| format!("card {id} doesn't exist").into())
 }
 
 pub fn card_front(bytes: &[u8]) -> Result<&str> {
@@ -156,13 +459,83 @@ pub fn card_front(bytes: &[u8]) -> Result<&str> {
     Ok(str::from_utf8(&bytes[..separator_idx])?)
 }
 
-pub fn cards_to_review(srs: &Srs, now_in_epoch_days: u16) -> Vec<CardIndex> {
-    srs.schedule
+/// The number of cards due on each of the coming days, from [forecast].
+#[derive(Debug)]
+pub struct Forecast {
+    /// Cards which were forgotten when they were last answered, and are due as soon as possible.
+    pub lapsed: usize,
+    /// Cards which were due before today, and haven't been reviewed yet.
+    pub overdue: usize,
+    /// The number of cards due on each day, starting from today. Lapsed and overdue cards aren't
+    /// included.
+    pub days: Vec<usize>,
+}
+
+impl Forecast {
+    /// Returns the length of a bar for each day in [Forecast::days], scaled so that the busiest day
+    /// has a bar of `full_width`. Lengths are rounded up, so that days with any cards have a bar.
+    pub fn bar_widths(&self, full_width: usize) -> Vec<usize> {
+        let max = self.days.iter().copied().max().unwrap_or_default();
+
+        self.days
+            .iter()
+            .map(|&count| match count {
+                0 => 0,
+                count => (count * full_width).div_ceil(max),
+            })
+            .collect()
+    }
+}
+
+/// Counts the cards matching the filter which are due on each of the given number of days,
+/// starting from today. Days after the last one that can be stored aren't included.
+pub fn forecast(srs: &Srs, now_in_epoch_days: u16, days: u16, filter: &Filter) -> Forecast {
+    let days = usize::from(days).min(usize::from(u16::MAX - now_in_epoch_days) + 1);
+    let mut forecast = Forecast {
+        lapsed: 0,
+        overdue: 0,
+        days: vec![0; days],
+    };
+
+    for (i, sched) in srs.schedule.iter().enumerate() {
+        if !filter.matches(srs, i as CardIndex) {
+            continue;
+        }
+
+        if sched.scheduled_for == 0 {
+            forecast.lapsed += 1;
+        } else if sched.scheduled_for < now_in_epoch_days {
+            forecast.overdue += 1;
+        } else if let Some(count) = forecast
+            .days
+            .get_mut(usize::from(sched.scheduled_for - now_in_epoch_days))
+        {
+            *count += 1;
+        }
+    }
+
+    forecast
+}
+
+/// Returns the cards which are due for review and match the given filter, leaving out any over
+/// the daily limits of their decks. See [limits] for how those are applied. No cards are due
+/// while vacation mode is on.
+pub fn cards_to_review(srs: &Srs, now_in_epoch_days: u16, filter: &Filter) -> Vec<CardIndex> {
+    if srs.vacation_start.is_some() {
+        return Vec::new();
+    }
+
+    let due = srs
+        .schedule
         .iter()
         .enumerate()
-        .filter(|&(_, sched)| sched.scheduled_for <= now_in_epoch_days)
+        .filter(|&(i, sched)| {
+            sched.scheduled_for <= now_in_epoch_days && filter.matches(srs, i as CardIndex)
+        })
         .map(|(i, _)| i as CardIndex)
-        .collect()
+        .collect();
+
+    limits::apply(srs, now_in_epoch_days, due)
 }
 
 pub fn card(srs: &Srs, i: CardIndex) -> Result<Card> {
@@ -183,7 +556,8 @@ pub fn card(srs: &Srs, i: CardIndex) -> Result<Card> {
         })?;
 
     let mut front = card.clone();
-    let back = front.split_off(separator_idx);
+    let back = front.split_off(separator_idx + 1);
+    front.pop();
 
     Ok(Card {
         front: String::from_utf8(front)?.into_boxed_str(),
@@ -195,183 +569,683 @@ pub fn apply_answers(
     srs: Srs,
     path: &Path,
     now_in_epoch_days: u16,
-    answers: &mut [Answer],
+    answers: &[Answer],
     rng: &mut Rng,
 ) -> Result<()> {
-    answers.sort_by_key(|k| k.card_index);
-
-    let mut stats = srs.stats;
-    let mut schedule = srs.schedule;
-
-    for answer in answers {
-        let idx = answer.card_index;
+    update(srs, path, |srs| {
+        let mut schedule = srs.schedule;
+        let mut history = srs.history.into_vec();
+
+        let deck_settings: HashMap<DeckId, Settings> = srs
+            .decks
+            .iter()
+            .map(|deck| (deck.id, deck.settings.unwrap_or(srs.settings)))
+            .collect();
+
+        // The number of cards due on each day, for balancing the load of due dates.
+        let mut due_counts: HashMap<u16, usize> = HashMap::new();
+        for sched in schedule.iter() {
+            *due_counts.entry(sched.scheduled_for).or_default() += 1;
+        }
 
-        let sched = schedule
-            .get_mut(usize::from(idx))
-            .expect("card wasn't deleted during review");
-        if answer.is_correct {
-            let last_was_correct = sched.scheduled_for != 0;
+        let mut last_answered: HashMap<CardId, u16> = history
+            .iter()
+            .map(|review| (review.card_id, review.day))
+            .collect();
 
-            let mut new_interval = if last_was_correct {
-                sched.most_recent_interval * 5
-            } else {
-                ((sched.most_recent_interval as f32) * WRONG_ANSWER_PENALTY).round() as u16
+        for answer in answers {
+            // Skip answers for cards which another process deleted during the review.
+            let Some(idx) = srs.ids.iter().position(|&id| id == answer.card_id) else {
+                continue;
             };
 
-            // Generate a number in -fuzz..=fuzz. This fuzz factor prevents cards from getting
-            // grouped together based on when they were added.
-            let max_fuzz = ((new_interval as f32) * 0.05).ceil() as u16;
-            let fuzz = rng.u16(max_fuzz);
+            let settings = deck_settings
+                .get(&srs.card_decks[idx])
+                .unwrap_or(&srs.settings);
+            let scheduler = settings.scheduler.scheduler();
+
+            let sched = &mut schedule[idx];
+            let previous_interval = sched.most_recent_interval;
+
+            let last_answered_day = last_answered
+                .get(&answer.card_id)
+                .copied()
+                .unwrap_or_else(|| sched.scheduled_for.saturating_sub(previous_interval));
+            let elapsed_days = now_in_epoch_days.saturating_sub(last_answered_day);
+
+            let outcome = scheduler.next(sched, answer.grade, elapsed_days);
+            let previous_day = sched.scheduled_for;
+            *sched = match outcome.interval {
+                Some(new_interval) => {
+                    let new_interval = fuzz::next_interval(
+                        new_interval,
+                        now_in_epoch_days,
+                        settings,
+                        &due_counts,
+                        rng,
+                    );
+
+                    CardSchedule {
+                        most_recent_interval: new_interval,
+                        // Cards due after the last day that can be stored are due on it instead.
+                        scheduled_for: now_in_epoch_days.saturating_add(new_interval),
+                        memory: outcome.memory,
+                    }
+                }
+                None => CardSchedule {
+                    scheduled_for: 0,
+                    memory: outcome.memory,
+                    ..*sched
+                },
+            };
 
-            if rng.bool() {
-                new_interval += fuzz;
-            } else {
-                new_interval -= fuzz;
+            if let Some(count) = due_counts.get_mut(&previous_day) {
+                *count -= 1;
             }
+            *due_counts.entry(sched.scheduled_for).or_default() += 1;
+            last_answered.insert(answer.card_id, now_in_epoch_days);
+
+            history.push(Review {
+                card_id: answer.card_id,
+                day: now_in_epoch_days,
+                previous_interval,
+                new_interval: sched.most_recent_interval,
+                grade: answer.grade,
+            });
+        }
 
-            new_interval = new_interval.max(1);
+        Ok(Srs {
+            schedule,
+            history: history.into_boxed_slice(),
+            ..srs
+        })
+    })
+}
 
-            *sched = CardSchedule {
-                most_recent_interval: new_interval,
-                scheduled_for: now_in_epoch_days + new_interval,
-            }
-        } else {
-            *sched = CardSchedule {
-                scheduled_for: 0,
-                ..*sched
-            }
-        }
+/// Replaces the settings of the database.
+pub fn set_settings(srs: Srs, path: &Path, settings: Settings) -> Result<()> {
+    update(srs, path, |srs| Ok(Srs { settings, ..srs }))
+}
 
-        let stat = match stats.get_mut(usize::from(sched.most_recent_interval)) {
-            Some(s) => s,
-            // The last bucket in stats covers all the intervals from that day onward.
-            None => stats.last_mut().unwrap(),
-        };
-        // Reset on wraparound
-        if stat.correct == u8::MAX || stat.wrong == u8::MAX {
-            *stat = Stat {
-                correct: 0,
-                wrong: 0,
-            }
-        }
+/// Returns the settings used for cards in the given deck.
+pub fn deck_settings(srs: &Srs, deck: DeckId) -> Settings {
+    srs.decks
+        .iter()
+        .find(|d| d.id == deck)
+        .and_then(|d| d.settings)
+        .unwrap_or(srs.settings)
+}
 
-        *stat = if answer.is_correct {
-            Stat {
-                correct: stat.correct + 1,
-                ..*stat
-            }
-        } else {
-            Stat {
-                wrong: stat.wrong + 1,
-                ..*stat
-            }
-        };
+/// Replaces the settings of the given deck. None makes the deck use the settings of the database.
+pub fn set_deck_settings(
+    srs: Srs,
+    path: &Path,
+    deck: DeckId,
+    settings: Option<Settings>,
+) -> Result<()> {
+    update(srs, path, |srs| {
+        let idx = deck_index(&srs, deck)?;
+
+        let mut decks = srs.decks.into_vec();
+        decks[idx].settings = settings;
+
+        Ok(Srs {
+            decks: decks.into_boxed_slice(),
+            ..srs
+        })
+    })
+}
+
+/// Returns the ID of the deck with the given name.
+pub fn deck_id(srs: &Srs, name: &str) -> Result<DeckId> {
+    srs.decks
+        .iter()
+        .find(|d| d.name == name)
+        .map(|d| d.id)
+        .ok_or_else(|| format!("deck '{name}' doesn't exist").into())
+}
+
+fn deck_index(srs: &Srs, id: DeckId) -> Result<usize> {
+    srs.decks
+        .iter()
+        .position(|d| d.id == id)
+        .ok_or_else(|| format!("deck {id} doesn't exist").into())
+}
+
+fn validate_deck_name(srs: &Srs, name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err("deck name can't be empty".into());
     }
 
-    write(path, srs.cards.into_vec(), &schedule, *stats)?;
+    if name.len() > MAX_DECK_NAME_LEN {
+        return Err(format!("deck name can't be longer than {MAX_DECK_NAME_LEN} bytes").into());
+    }
+
+    if srs.decks.iter().any(|d| d.name == name) {
+        return Err(format!("deck '{name}' already exists").into());
+    }
 
     Ok(())
 }
 
-pub fn open(p: &Path) -> Result<Srs> {
-    const NUM_CARDS_BYTES: usize = 2;
-    const CARD_LENGTH_BYTES: usize = 2;
+/// Adds an empty deck which uses the settings of the database, and returns its ID.
+pub fn create_deck(srs: Srs, path: &Path, name: String) -> Result<DeckId> {
+    let mut id = DEFAULT_DECK;
+    update(srs, path, |srs| {
+        validate_deck_name(&srs, &name)?;
+
+        id = srs
+            .decks
+            .iter()
+            .map(|d| d.id)
+            .max()
+            .unwrap_or(DEFAULT_DECK)
+            .checked_add(1)
+            .ok_or("reached deck count limit")?;
+
+        let mut decks = srs.decks.into_vec();
+        decks.push(Deck {
+            id,
+            name: name.clone(),
+            settings: None,
+        });
 
-    const SCHEDULE_ROW_BYTES: usize = 4;
+        Ok(Srs {
+            decks: decks.into_boxed_slice(),
+            ..srs
+        })
+    })?;
 
-    const STAT_ROW_BYTES: usize = 2;
-    const STAT_BYTES: usize = STAT_ROW_BYTES * STAT_ROW_COUNT;
+    Ok(id)
+}
 
-    const MIN_SIZE_BYTES: usize = NUM_CARDS_BYTES + STAT_BYTES;
+pub fn rename_deck(srs: Srs, path: &Path, id: DeckId, name: String) -> Result<()> {
+    update(srs, path, |srs| {
+        let idx = deck_index(&srs, id)?;
+        validate_deck_name(&srs, &name)?;
 
-    let bytes = std::fs::read(p)?;
-    if bytes.len() < MIN_SIZE_BYTES {
-        return Err(format!(
-            "read {} < {} bytes from {}",
-            bytes.len(),
-            MIN_SIZE_BYTES,
-            p.to_string_lossy(),
-        )
-        .into());
+        let mut decks = srs.decks.into_vec();
+        decks[idx].name = name.clone();
+
+        Ok(Srs {
+            decks: decks.into_boxed_slice(),
+            ..srs
+        })
+    })
+}
+
+/// Deletes the given deck, moving its cards to the [DEFAULT_DECK].
+pub fn delete_deck(srs: Srs, path: &Path, id: DeckId) -> Result<()> {
+    if id == DEFAULT_DECK {
+        return Err("the default deck can't be deleted".into());
     }
 
-    let num_cards = usize::from(u16::from_le_bytes([bytes[0], bytes[1]]));
+    update(srs, path, |srs| {
+        let idx = deck_index(&srs, id)?;
 
-    let mut stats = [Stat::default(); STAT_ROW_COUNT];
-    bytes[NUM_CARDS_BYTES..][..STAT_BYTES]
-        .chunks_exact(STAT_ROW_BYTES)
-        .enumerate()
-        .for_each(|(i, chunk)| {
-            stats[i] = Stat {
-                correct: chunk[0],
-                wrong: chunk[1],
-            }
-        });
+        let mut decks = srs.decks.into_vec();
+        decks.remove(idx);
 
-    let schedule_start_offset = MIN_SIZE_BYTES;
+        let card_decks = srs
+            .card_decks
+            .iter()
+            .map(|&d| if d == id { DEFAULT_DECK } else { d })
+            .collect();
 
-    let schedule = bytes[schedule_start_offset..][..num_cards * SCHEDULE_ROW_BYTES]
-        .chunks_exact(SCHEDULE_ROW_BYTES)
-        .map(|chunk| CardSchedule {
-            most_recent_interval: u16::from_le_bytes([chunk[0], chunk[1]]),
-            scheduled_for: u16::from_le_bytes([chunk[2], chunk[3]]),
+        Ok(Srs {
+            decks: decks.into_boxed_slice(),
+            card_decks,
+            ..srs
         })
+    })
+}
+
+/// Replaces an empty database with the given one, like one read by [export::from_json]. Fails if
+/// the database has any cards or review history.
+pub fn import_database(srs: Srs, path: &Path, imported: Srs) -> Result<()> {
+    if !srs.cards.is_empty() || !srs.history.is_empty() {
+        return Err("a JSON export can only be imported into an empty database".into());
+    }
+
+    write(
+        path,
+        &Srs {
+            generation: srs.generation,
+            ..imported
+        },
+    )
+}
+
+/// Moves the card with the given ID to the given deck. Its schedule is unchanged.
+pub fn move_card(srs: Srs, path: &Path, card_id: CardId, deck: DeckId) -> Result<()> {
+    update(srs, path, |srs| {
+        let idx = usize::from(card_index(&srs, card_id)?);
+        deck_index(&srs, deck)?;
+
+        let mut card_decks = srs.card_decks;
+        card_decks[idx] = deck;
+
+        Ok(Srs { card_decks, ..srs })
+    })
+}
+
+/// Returns the number of correct and wrong answers, bucketed by the interval of the card after the
+/// answer. The last bucket covers all the intervals from that day onward.
+///
+/// When the filter restricts which cards are included, only answers to the cards which currently
+/// match it are counted. That leaves out [Srs::legacy_stats], since they weren't recorded per card.
+pub fn stats(srs: &Srs, filter: &Filter) -> Box<Stats> {
+    if filter.is_empty() {
+        return count_answers(srs.legacy_stats.clone(), &srs.history);
+    }
+
+    let matching_ids: HashSet<CardId> = srs
+        .ids
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| filter.matches(srs, i as CardIndex))
+        .map(|(_, &id)| id)
+        .collect();
+
+    let history: Vec<Review> = srs
+        .history
+        .iter()
+        .filter(|review| matching_ids.contains(&review.card_id))
+        .copied()
         .collect();
 
-    let cards_start_offset = schedule_start_offset + (num_cards * SCHEDULE_ROW_BYTES);
+    count_answers(Box::new([Stat::default(); STAT_ROW_COUNT]), &history)
+}
+
+fn count_answers(mut stats: Box<Stats>, history: &[Review]) -> Box<Stats> {
+    for review in history {
+        let stat = match stats.get_mut(usize::from(review.new_interval)) {
+            Some(s) => s,
+            None => stats.last_mut().unwrap(),
+        };
+
+        if review.grade.is_correct() {
+            stat.correct += 1;
+        } else {
+            stat.wrong += 1;
+        }
+    }
+
+    stats
+}
+
+pub fn open(p: &Path) -> Result<Srs> {
+    let bytes = format::upgrade(fs::read(p)?)?;
+
+    format::decode(&bytes)
+        .map_err(|e| format!("failed to read {}: {e}", p.to_string_lossy()).into())
+}
+
+/// Scans the database at the given path for problems, like corrupted sections or cards which
+/// can't be displayed. The offsets of problems are relative to the file after upgrading it to the
+/// current format version.
+pub fn check(p: &Path, now_in_epoch_days: u16) -> Result<Vec<Problem>> {
+    let bytes = format::upgrade(fs::read(p)?)?;
+
+    format::check(&bytes, now_in_epoch_days)
+}
 
-    let mut cards = Vec::with_capacity(num_cards);
-    let mut cards_bytes = &bytes[cards_start_offset..];
-    while !cards_bytes.is_empty() {
-        let length = usize::from(u16::from_le_bytes([cards_bytes[0], cards_bytes[1]]));
-        cards_bytes = &cards_bytes[CARD_LENGTH_BYTES..];
+/// Writes the given database to the given path.
+///
+/// Fails with [Conflict] if another process wrote to the database after `srs` was read from it.
+/// Writes from other processes are blocked until this is done.
+pub fn write(path: &Path, srs: &Srs) -> Result<()> {
+    let _lock = lock::exclusive(path)?;
 
-        cards.push(cards_bytes[..length].to_vec());
-        cards_bytes = &cards_bytes[length..];
+    if generation_on_disk(path).is_some_and(|g| g != srs.generation) {
+        return Err(Conflict.into());
     }
 
-    Ok(Srs {
-        cards: cards.into_boxed_slice(),
-        schedule,
-        stats: Box::new(stats),
+    replace(path, |out| {
+        format::encode(out, srs, srs.generation.wrapping_add(1))
     })
 }
 
-pub fn write(
+/// Applies `change` to `srs`, and writes the result. If another process wrote to the database
+/// after `srs` was read, `change` is applied to the latest version of the database instead, so
+/// that the changes from both are kept.
+fn update(srs: Srs, path: &Path, mut change: impl FnMut(Srs) -> Result<Srs>) -> Result<()> {
+    match write(path, &change(srs)?) {
+        Err(e) if e.is::<Conflict>() => write(path, &change(open(path)?)?),
+        result => result,
+    }
+}
+
+/// Returns the generation of the database at the given path, or None if there isn't a readable
+/// database there.
+fn generation_on_disk(path: &Path) -> Option<u32> {
+    let bytes = format::upgrade(fs::read(path).ok()?).ok()?;
+
+    format::decode(&bytes).ok().map(|srs| srs.generation)
+}
+
+/// Returns the backups of the database at the given path which currently exist, from most to
+/// least recent, along with the number used to [restore] them.
+pub fn backups(path: &Path) -> Vec<(u8, PathBuf)> {
+    (1..=backup::COUNT)
+        .map(|n| (n, backup::path(path, n)))
+        .filter(|(_, p)| p.exists())
+        .collect()
+}
+
+/// Replaces the database at the given path with the nth most recent backup of it. The current
+/// contents of the database become the most recent backup, so a restore can be undone by
+/// restoring backup 1.
+pub fn restore(path: &Path, n: u8) -> Result<()> {
+    let backup_path = backup::path(path, n);
+    let mut srs = open(&backup_path).map_err(|e| format!("backup {n} is unusable: {e}"))?;
+
+    if let Some(generation) = generation_on_disk(path) {
+        srs.generation = generation;
+    }
+
+    write(path, &srs)
+}
+
+/// Atomically replaces the file at the given path with the contents written by `write_contents`.
+///
+/// The new contents are written to a temporary file in the same directory, and synced to disk
+/// before being renamed over the existing file, so a crash at any point leaves either the old or
+/// the new contents in place. The previous contents are kept as a backup.
+fn replace(
     path: &Path,
-    cards: Vec<Vec<u8>>,
-    schedule: &[CardSchedule],
-    stats: Stats,
+    write_contents: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
 ) -> Result<()> {
-    let num_cards: u16 = cards.len().try_into().unwrap();
+    let tmp_path = tmp::path_beside(path);
+
+    let result = write_synced(&tmp_path, write_contents)
+        .and_then(|_| backup::rotate(path))
+        .and_then(|_| Ok(fs::rename(&tmp_path, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
 
-    let tmp_path = tmp::path();
-    {
-        let new_file = File::create(&tmp_path)?;
-        let mut new_buf = BufWriter::with_capacity(256 * 1024, new_file);
+    // Sync the directory so that the rename itself is durable.
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
 
-        // Fixed header
-        new_buf.write_all(&num_cards.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_synced(
+    path: &Path,
+    write_contents: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let new_file = File::options().write(true).create_new(true).open(path)?;
+    let mut new_buf = BufWriter::with_capacity(256 * 1024, new_file);
+
+    write_contents(&mut new_buf)?;
+
+    let new_file = new_buf.into_inner().map_err(|e| e.into_error())?;
+    new_file.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const TODAY: u16 = 20_000;
+
+    /// A directory for a database, which is deleted along with everything in it when this is
+    /// dropped.
+    pub(crate) struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub(crate) fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        for stat in stats {
-            new_buf.write_all(&[stat.correct, stat.wrong])?;
+            let dir = env::temp_dir().join(format!(
+                "srs-cli-test-{}-{}",
+                process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed),
+            ));
+            fs::create_dir_all(&dir).unwrap();
+
+            Self(dir)
+        }
+
+        pub(crate) fn db(&self) -> PathBuf {
+            self.0.join("srs.db")
         }
+    }
 
-        // Schedule
-        for s in schedule {
-            new_buf.write_all(&s.most_recent_interval.to_le_bytes())?;
-            new_buf.write_all(&s.scheduled_for.to_le_bytes())?;
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
         }
+    }
 
-        // Cards
-        for card in cards {
-            let length: u16 = card.len().try_into().unwrap();
-            new_buf.write_all(&length.to_le_bytes())?;
-            new_buf.write_all(&card)?;
+    fn new_card(front: &str) -> NewCard {
+        NewCard {
+            front: front.to_string(),
+            back: String::new(),
+            deck: DEFAULT_DECK,
+            tags: Vec::new(),
         }
     }
 
-    fs::rename(tmp_path, path)?;
+    /// Adds a card to the latest version of the database at the given path.
+    pub(crate) fn add(path: &Path, front: &str) {
+        let srs = open(path).unwrap_or_default();
+        add_cards(srs, path, TODAY, &[new_card(front)]).unwrap();
+    }
 
-    Ok(())
+    fn fronts(srs: &Srs) -> Vec<&str> {
+        srs.cards.iter().map(|c| card_front(c).unwrap()).collect()
+    }
+
+    #[test]
+    fn writing_a_stale_database_conflicts() {
+        let dir = TempDir::new();
+        let path = dir.db();
+        add_cards(Srs::default(), &path, TODAY, &[new_card("a")]).unwrap();
+
+        let stale = open(&path).unwrap();
+        add_cards(open(&path).unwrap(), &path, TODAY, &[new_card("b")]).unwrap();
+
+        let err = write(&path, &stale).unwrap_err();
+        assert!(err.is::<Conflict>(), "{err}");
+        assert_eq!(fronts(&open(&path).unwrap()), ["a", "b"]);
+    }
+
+    #[test]
+    fn updates_merge_changes_from_other_processes() {
+        let dir = TempDir::new();
+        let path = dir.db();
+        add_cards(Srs::default(), &path, TODAY, &[new_card("a")]).unwrap();
+
+        let stale = open(&path).unwrap();
+        add_cards(open(&path).unwrap(), &path, TODAY, &[new_card("b")]).unwrap();
+        add_cards(stale, &path, TODAY, &[new_card("c")]).unwrap();
+
+        let srs = open(&path).unwrap();
+        assert_eq!(fronts(&srs), ["a", "b", "c"]);
+        assert_eq!(*srs.ids, [0, 1, 2]);
+    }
+
+    #[test]
+    fn only_the_most_recent_backups_are_kept() {
+        let dir = TempDir::new();
+        let path = dir.db();
+
+        // Each write adds one card, so a backup made after n writes has n cards.
+        for front in ["1", "2", "3", "4", "5", "6", "7"] {
+            add(&path, front);
+        }
+
+        let backups: Vec<(u8, usize)> = backups(&path)
+            .into_iter()
+            .map(|(n, p)| (n, open(&p).unwrap().cards.len()))
+            .collect();
+        assert_eq!(backups, [(1, 6), (2, 5), (3, 4), (4, 3), (5, 2)]);
+        assert!(!backup::path(&path, backup::COUNT + 1).exists());
+    }
+
+    #[test]
+    fn restoring_keeps_the_replaced_database_as_a_backup() {
+        let dir = TempDir::new();
+        let path = dir.db();
+        for front in ["1", "2", "3"] {
+            add(&path, front);
+        }
+
+        restore(&path, 2).unwrap();
+        assert_eq!(fronts(&open(&path).unwrap()), ["1"]);
+        let backup = open(&backup::path(&path, 1)).unwrap();
+        assert_eq!(fronts(&backup), ["1", "2", "3"]);
+
+        restore(&path, 1).unwrap();
+        assert_eq!(fronts(&open(&path).unwrap()), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn ids_survive_deleting_other_cards() {
+        let dir = TempDir::new();
+        let path = dir.db();
+        for front in ["a", "b", "c"] {
+            add(&path, front);
+        }
+
+        delete_card(open(&path).unwrap(), &path, 1).unwrap();
+        let srs = open(&path).unwrap();
+        assert_eq!(fronts(&srs), ["a", "c"]);
+        assert_eq!(*srs.ids, [0, 2]);
+        assert_eq!(card_index(&srs, 2).unwrap(), 1);
+        assert!(card_index(&srs, 1).is_err());
+
+        add(&path, "d");
+        let srs = open(&path).unwrap();
+        assert_eq!(*srs.ids, [0, 2, 3]);
+    }
+
+    #[test]
+    fn answers_near_the_last_day_saturate() {
+        let today = u16::MAX - 5;
+
+        for max_interval in [None, Some(36_500)] {
+            for scheduler in SchedulerKind::ALL {
+                let dir = TempDir::new();
+                let path = dir.db();
+                add(&path, "a");
+
+                let settings = Settings {
+                    scheduler,
+                    max_interval,
+                    ..Default::default()
+                };
+                let srs = open(&path).unwrap();
+                let srs = Srs {
+                    schedule: Box::new([CardSchedule {
+                        most_recent_interval: u16::MAX - 10,
+                        scheduled_for: today,
+                        memory: Memory {
+                            ease: 2500,
+                            stability: 60_000.0,
+                            difficulty: 5.0,
+                        },
+                    }]),
+                    settings,
+                    ..srs
+                };
+                write(&path, &srs).unwrap();
+
+                let answers = [Answer {
+                    card_id: 0,
+                    grade: Grade::Easy,
+                }];
+                let mut rng = Rng::with_seed(7);
+                apply_answers(open(&path).unwrap(), &path, today, &answers, &mut rng).unwrap();
+
+                let sched = open(&path).unwrap().schedule[0];
+                // Fuzz can move the interval down from the cap by up to 5%.
+                let cap = max_interval.unwrap_or(u16::MAX);
+                assert!(
+                    (cap - cap / 10..=cap).contains(&sched.most_recent_interval),
+                    "{scheduler}: {sched:?}",
+                );
+                assert_eq!(sched.scheduled_for, u16::MAX, "{scheduler}: {sched:?}");
+            }
+        }
+    }
+
+    fn scheduled(scheduled_for: &[u16]) -> Srs {
+        Srs {
+            schedule: scheduled_for
+                .iter()
+                .map(|&scheduled_for| CardSchedule {
+                    most_recent_interval: 10,
+                    scheduled_for,
+                    memory: Memory::default(),
+                })
+                .collect(),
+            card_decks: scheduled_for.iter().map(|_| DEFAULT_DECK).collect(),
+            card_tags: scheduled_for.iter().map(|_| Vec::new()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn forecast_counts_cards_by_day() {
+        let srs = scheduled(&[0, TODAY - 3, TODAY - 1, TODAY, TODAY, TODAY + 2, TODAY + 5]);
+
+        let forecast = forecast(&srs, TODAY, 5, &Filter::default());
+
+        assert_eq!(forecast.lapsed, 1);
+        assert_eq!(forecast.overdue, 2);
+        // The card due in 5 days is past the end of the forecast.
+        assert_eq!(forecast.days, [2, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn forecast_stops_at_the_last_day() {
+        let srs = scheduled(&[u16::MAX]);
+
+        let forecast = forecast(&srs, u16::MAX - 2, 30, &Filter::default());
+
+        assert_eq!(forecast.days, [0, 0, 1]);
+    }
+
+    #[test]
+    fn forecast_bars_are_scaled_to_the_busiest_day() {
+        let forecast = Forecast {
+            lapsed: 0,
+            overdue: 0,
+            days: vec![0, 1, 50, 100, 99],
+        };
+
+        assert_eq!(forecast.bar_widths(10), [0, 1, 5, 10, 10]);
+
+        let empty = Forecast {
+            days: vec![0, 0],
+            ..forecast
+        };
+        assert_eq!(empty.bar_widths(10), [0, 0]);
+    }
+
+    #[test]
+    fn the_separator_isnt_part_of_either_side() {
+        let srs = Srs {
+            cards: Box::new([encode_card("front", "back").unwrap()]),
+            ..Default::default()
+        };
+
+        let Card { front, back } = card(&srs, 0).unwrap();
+
+        assert_eq!(&*front, "front");
+        assert_eq!(&*back, "back");
+    }
 }
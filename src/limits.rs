@@ -0,0 +1,321 @@
+//! Daily limits on how many reviews and new cards are shown each day, set per deck. When more
+//! reviews are due than the limit allows, the [OverflowOrder] of the deck decides which are shown.
+//! Cards over a limit stay due, so they're carried over to the next day, ahead of the cards which
+//! only become due then.
+//!
+//! Limits apply separately to new cards, which have never been answered, and to reviews of every
+//! other card. Whether a card is new isn't stored: it's derived from the history and schedule, so
+//! it also works for cards added by older versions.
+
+use crate::{CardId, CardIndex, CardSchedule, DeckId, Srs, deck_settings, scheduler::Memory};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// The largest daily limit that can be stored. The database uses [u16::MAX] for no limit.
+pub const MAX_LIMIT: u16 = u16::MAX - 1;
+
+/// Which due reviews are kept for the day when there are more than the review limit. The rest
+/// are carried over to the next day.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowOrder {
+    /// The cards which were due the longest ago, with lapsed cards first.
+    #[default]
+    MostOverdue,
+    /// The cards with the shortest intervals, which are the most likely to have been forgotten.
+    LowestInterval,
+}
+
+impl OverflowOrder {
+    pub const ALL: [Self; 2] = [Self::MostOverdue, Self::LowestInterval];
+
+    /// The value used to store this in the database.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::MostOverdue => 0,
+            Self::LowestInterval => 1,
+        }
+    }
+
+    pub fn from_byte(b: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|o| o.to_byte() == b)
+    }
+}
+
+impl fmt::Display for OverflowOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::MostOverdue => "overdue",
+            Self::LowestInterval => "interval",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for OverflowOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|o| o.to_string() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|o| o.to_string()).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    New,
+    Review,
+}
+
+/// Returns whether the card has never been answered.
+pub fn is_new(srs: &Srs, i: CardIndex) -> bool {
+    let id = srs.ids[usize::from(i)];
+
+    !srs.history.iter().any(|review| review.card_id == id)
+        && has_initial_schedule(&srs.schedule[usize::from(i)])
+}
+
+/// Returns whether the schedule is the one cards are given when they're added. Cards answered
+/// before the history was kept have no reviews in it, but they'll have a different schedule
+/// unless every answer left them where they started.
+fn has_initial_schedule(sched: &CardSchedule) -> bool {
+    sched.most_recent_interval == 1 && sched.scheduled_for != 0 && sched.memory == Memory::default()
+}
+
+/// Returns the due cards that are within the daily limits of their decks, in the same order.
+///
+/// Answers given earlier in the day count towards the limits. Cards which were already answered
+/// today are always kept, so that ones answered wrong can be relearned.
+pub(crate) fn apply(srs: &Srs, now_in_epoch_days: u16, due: Vec<CardIndex>) -> Vec<CardIndex> {
+    let mut first_answered: HashMap<CardId, u16> = HashMap::new();
+    for review in srs.history.iter() {
+        first_answered.entry(review.card_id).or_insert(review.day);
+    }
+
+    let answered_today: HashSet<CardId> = srs
+        .history
+        .iter()
+        .filter(|review| review.day == now_in_epoch_days)
+        .map(|review| review.card_id)
+        .collect();
+
+    let indices: HashMap<CardId, usize> =
+        srs.ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+
+    let mut answered_counts: HashMap<(DeckId, Kind), u16> = HashMap::new();
+    for id in &answered_today {
+        // Cards which have been deleted since don't count.
+        let Some(&i) = indices.get(id) else {
+            continue;
+        };
+
+        let kind = if first_answered[id] == now_in_epoch_days {
+            Kind::New
+        } else {
+            Kind::Review
+        };
+        *answered_counts
+            .entry((srs.card_decks[i], kind))
+            .or_default() += 1;
+    }
+
+    let mut kept = HashSet::new();
+    let mut queues: HashMap<(DeckId, Kind), Vec<CardIndex>> = HashMap::new();
+    for &i in &due {
+        let id = srs.ids[usize::from(i)];
+        if answered_today.contains(&id) {
+            kept.insert(i);
+            continue;
+        }
+
+        let kind = if !first_answered.contains_key(&id)
+            && has_initial_schedule(&srs.schedule[usize::from(i)])
+        {
+            Kind::New
+        } else {
+            Kind::Review
+        };
+        queues
+            .entry((srs.card_decks[usize::from(i)], kind))
+            .or_default()
+            .push(i);
+    }
+
+    for ((deck, kind), mut queue) in queues {
+        let settings = deck_settings(srs, deck);
+        let limit = match kind {
+            Kind::New => settings.new_limit,
+            Kind::Review => settings.review_limit,
+        };
+
+        if let Some(limit) = limit {
+            let schedule = |i: &CardIndex| &srs.schedule[usize::from(*i)];
+            match (kind, settings.overflow_order) {
+                // New cards are introduced in the order they were added.
+                (Kind::New, _) => queue.sort_by_key(|&i| srs.ids[usize::from(i)]),
+                (Kind::Review, OverflowOrder::MostOverdue) => {
+                    queue.sort_by_key(|i| schedule(i).scheduled_for);
+                }
+                (Kind::Review, OverflowOrder::LowestInterval) => queue
+                    .sort_by_key(|i| (schedule(i).most_recent_interval, schedule(i).scheduled_for)),
+            }
+
+            let answered = answered_counts.get(&(deck, kind)).copied().unwrap_or(0);
+            queue.truncate(usize::from(limit.saturating_sub(answered)));
+        }
+
+        kept.extend(queue);
+    }
+
+    due.into_iter().filter(|i| kept.contains(i)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Deck, Grade, Review, Settings};
+
+    const TODAY: u16 = 20_000;
+
+    /// Returns a database with a card for each (interval, scheduled for) pair, with IDs matching
+    /// their positions. Cards with an interval of 1 are new unless they're in the history.
+    fn srs(schedule: &[(u16, u16)], settings: Settings) -> Srs {
+        let n = schedule.len();
+
+        Srs {
+            cards: vec![b"front\0back".to_vec(); n].into_boxed_slice(),
+            schedule: schedule
+                .iter()
+                .map(|&(most_recent_interval, scheduled_for)| CardSchedule {
+                    most_recent_interval,
+                    scheduled_for,
+                    memory: Memory::default(),
+                })
+                .collect(),
+            ids: (0..n as CardId).collect(),
+            next_id: n as CardId,
+            settings,
+            card_decks: vec![0; n].into_boxed_slice(),
+            card_tags: vec![Vec::new(); n].into_boxed_slice(),
+            ..Default::default()
+        }
+    }
+
+    fn review(card_id: CardId, day: u16) -> Review {
+        Review {
+            card_id,
+            day,
+            previous_interval: 1,
+            new_interval: 5,
+            grade: Grade::Good,
+        }
+    }
+
+    fn all(srs: &Srs) -> Vec<CardIndex> {
+        (0..srs.cards.len() as CardIndex).collect()
+    }
+
+    #[test]
+    fn no_limits_keeps_every_card() {
+        let srs = srs(&[(1, TODAY), (5, TODAY - 3), (1, 0)], Settings::default());
+
+        assert_eq!(apply(&srs, TODAY, all(&srs)), [0, 1, 2]);
+    }
+
+    #[test]
+    fn reviews_overflow_in_the_chosen_order() {
+        let schedule = [(20, TODAY - 1), (5, TODAY - 2), (40, TODAY - 9), (9, 0)];
+
+        let most_overdue = srs(
+            &schedule,
+            Settings {
+                review_limit: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(apply(&most_overdue, TODAY, all(&most_overdue)), [2, 3]);
+
+        let lowest_interval = srs(
+            &schedule,
+            Settings {
+                review_limit: Some(2),
+                overflow_order: OverflowOrder::LowestInterval,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            apply(&lowest_interval, TODAY, all(&lowest_interval)),
+            [1, 3]
+        );
+    }
+
+    #[test]
+    fn new_cards_have_their_own_limit() {
+        let mut srs = srs(
+            &[(1, TODAY), (1, TODAY), (5, TODAY), (1, TODAY), (1, TODAY)],
+            Settings {
+                new_limit: Some(2),
+                review_limit: Some(1),
+                ..Default::default()
+            },
+        );
+        // Card 3 was answered before, and stayed at an interval of 1, so it's a review.
+        srs.history = Box::new([review(3, TODAY - 1)]);
+
+        assert!(is_new(&srs, 0));
+        assert!(!is_new(&srs, 2));
+        assert!(!is_new(&srs, 3));
+        assert_eq!(apply(&srs, TODAY, all(&srs)), [0, 1, 2]);
+    }
+
+    #[test]
+    fn answers_from_earlier_in_the_day_count() {
+        let mut srs = srs(
+            &[(5, TODAY), (1, 0), (1, TODAY), (1, TODAY), (7, TODAY)],
+            Settings {
+                new_limit: Some(2),
+                review_limit: Some(2),
+                ..Default::default()
+            },
+        );
+        // Card 1 was new this morning and lapsed, and card 4's review was answered yesterday and
+        // today.
+        srs.history = Box::new([review(1, TODAY), review(4, TODAY - 1), review(4, TODAY)]);
+
+        // Card 1 is kept even though it used up one of the new cards, and card 4 used up one of
+        // the reviews.
+        assert_eq!(apply(&srs, TODAY, all(&srs)), [0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn limits_apply_per_deck() {
+        let mut srs = srs(
+            &[(5, TODAY), (5, TODAY), (5, TODAY), (5, TODAY)],
+            Settings {
+                review_limit: Some(1),
+                ..Default::default()
+            },
+        );
+        srs.card_decks = Box::new([0, 1, 1, 0]);
+        srs.decks = Box::new([
+            Deck {
+                id: 0,
+                name: "default".to_string(),
+                settings: None,
+            },
+            Deck {
+                id: 1,
+                name: "other".to_string(),
+                settings: Some(Settings::default()),
+            },
+        ]);
+
+        assert_eq!(apply(&srs, TODAY, all(&srs)), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,44 @@
+//! Advisory locking of the database file, to prevent multiple processes from writing to it at
+//! the same time.
+
+use crate::error::Result;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// An exclusive lock on a database. It's released when this is dropped.
+pub struct Lock {
+    file: File,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock anyway, so there's nothing to do if this fails.
+        let _ = self.file.unlock();
+    }
+}
+
+/// Waits until no other process holds the lock for the database at the given path, then takes
+/// it.
+///
+/// The lock is taken on a separate file, since the database itself is replaced on every write.
+pub fn exclusive(db_path: &Path) -> Result<Lock> {
+    let lock_path = path(db_path);
+    let file = File::options()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| format!("failed to open {}: {e}", lock_path.to_string_lossy()))?;
+
+    file.lock()
+        .map_err(|e| format!("failed to lock {}: {e}", lock_path.to_string_lossy()))?;
+
+    Ok(Lock { file })
+}
+
+fn path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".lock");
+
+    PathBuf::from(path)
+}
@@ -1,28 +1,50 @@
 mod opt;
+mod output;
 mod time;
 
+use opt::{DeckAction, FilterArgs, SettingsArgs, VacationAction};
+use output::Output;
+use srs_cli::date;
+use srs_cli::dedupe;
 use srs_cli::editor;
 use srs_cli::error::Result;
+use srs_cli::export;
+use srs_cli::import::{self, Record};
+use srs_cli::journal::{self, Journal};
+use srs_cli::json::Value;
+use srs_cli::limits;
 use srs_cli::prompt;
+use srs_cli::queue::Queue;
 use srs_cli::rand::Rng;
+use srs_cli::search::{self, Query};
+use srs_cli::tags;
+use srs_cli::vacation;
 use srs_cli::Answer;
 use srs_cli::Card;
+use srs_cli::CardId;
 use srs_cli::CardIndex;
+use srs_cli::DeckId;
+use srs_cli::Filter;
+use srs_cli::Grade;
+use srs_cli::NewCard;
+use srs_cli::Settings;
 use srs_cli::Srs;
-use std::io::{self, BufWriter, Write};
+use std::collections::HashSet;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::ops::Range;
 use std::path::Path;
 use time::now_in_epoch_days;
 
 fn main() -> Result<()> {
     let opt = opt::Opt::from_args();
 
-    let srs = match srs_cli::open(&opt.path) {
-        Ok(s) => s,
+    let srs = || match srs_cli::open(&opt.path) {
+        Ok(s) => apply_journal(s, &opt.path),
         Err(e) => {
             if !opt.path.exists() {
-                Srs::default()
+                Ok(Srs::default())
             } else {
-                return Err(e);
+                Err(e)
             }
         }
     };
@@ -30,12 +52,67 @@ fn main() -> Result<()> {
     use opt::Subcommand::*;
 
     let result = match &opt.subcommand {
-        Add => add_card(srs, &opt.path),
-        List => list(srs),
-        Delete { card_id } => delete_card(srs, &opt.path, *card_id),
-        Edit { card_id } => edit_card(srs, &opt.path, *card_id),
-        Review => review(srs, &opt.path),
-        Stats => stats(srs),
+        Add {
+            deck,
+            front: Some(front),
+            back,
+            ..
+        } => add_card_from_args(
+            srs()?,
+            &opt.path,
+            deck.as_deref(),
+            front,
+            back.as_deref().unwrap_or_default(),
+        ),
+        Add {
+            deck, stdin: true, ..
+        } => add_cards_from_stdin(srs()?, &opt.path, deck.as_deref()),
+        Add { deck, .. } => add_card(srs()?, &opt.path, deck.as_deref()),
+        Check => check(&opt.path),
+        Config {
+            deck,
+            settings,
+            inherit,
+        } => config(srs()?, &opt.path, deck.as_deref(), settings, *inherit),
+        Deck(action) => deck(srs()?, &opt.path, action),
+        Dedupe { filter } => dedupe(srs()?, &opt.path, filter),
+        Export { format, filter } => export(srs()?, *format, filter),
+        Forecast {
+            days,
+            chart,
+            filter,
+        } => forecast(srs()?, *days, *chart, filter, opt.output),
+        Import {
+            file,
+            format,
+            deck,
+            dry_run,
+            allow_duplicates,
+        } => import(
+            srs()?,
+            &opt.path,
+            file,
+            *format,
+            deck.as_deref(),
+            *dry_run,
+            *allow_duplicates,
+        ),
+        Due { filter } => due(srs()?, filter, opt.output),
+        List { filter } => list(srs()?, filter, opt.output),
+        Move { card_id, deck } => move_card(srs()?, &opt.path, *card_id, deck),
+        Delete { card_id } => delete_card(srs()?, &opt.path, *card_id),
+        Edit { card_id } => edit_card(srs()?, &opt.path, *card_id),
+        Restore { backup } => restore(&opt.path, *backup),
+        Search {
+            query,
+            mode,
+            filter,
+        } => search(srs()?, query, *mode, filter),
+        Review { gap, filter } => review(srs()?, &opt.path, *gap, filter),
+        Shift { days } => shift(srs()?, &opt.path, *days),
+        Show { card_id } => show(srs()?, *card_id),
+        Stats { filter } => stats(srs()?, filter, opt.output),
+        Vacation(action) => vacation(srs()?, &opt.path, action),
     };
 
     if let Err(err) = result {
@@ -48,54 +125,718 @@ fn main() -> Result<()> {
     }
 }
 
-fn add_card(srs: Srs, path: &Path) -> Result<()> {
-    let (front, back) = open_editor("", "")?;
+fn add_card(srs: Srs, path: &Path, deck: Option<&str>) -> Result<()> {
+    let deck = deck_filter(&srs, deck)?.unwrap_or(srs_cli::DEFAULT_DECK);
 
-    srs_cli::add_card(srs, path, now_in_epoch_days(), front, back)
+    let (mut front, mut back, mut tags) = open_editor("", "", &[])?;
+
+    loop {
+        match check_duplicates(&srs, &front)? {
+            DuplicateAction::Add => break,
+            DuplicateAction::Edit => (front, back, tags) = open_editor(&front, &back, &tags)?,
+            DuplicateAction::Cancel => {
+                println!("The card wasn't added.");
+                return Ok(());
+            }
+        }
+    }
+
+    let id = srs_cli::add_card(srs, path, now_in_epoch_days(), deck, front, back, tags)?;
+    println!("Added card {id}.");
+
+    Ok(())
+}
+
+fn add_card_from_args(
+    srs: Srs,
+    path: &Path,
+    deck: Option<&str>,
+    front: &str,
+    back: &str,
+) -> Result<()> {
+    let deck = deck_filter(&srs, deck)?.unwrap_or(srs_cli::DEFAULT_DECK);
+
+    let (front, back) = (front.trim().to_string(), back.trim().to_string());
+    if front.is_empty() {
+        return Err("Front of card can't be empty".into());
+    }
+    warn_about_duplicates(&srs, &front, None)?;
+
+    let now = now_in_epoch_days();
+    let id = srs_cli::add_card(srs, path, now, deck, front, back, Vec::new())?;
+    println!("Added card {id}.");
+
+    Ok(())
+}
+
+/// Adds the cards read from stdin, in the format of the editor template. Cards are separated by
+/// the same divider as their front and back.
+fn add_cards_from_stdin(srs: Srs, path: &Path, deck: Option<&str>) -> Result<()> {
+    let deck = deck_filter(&srs, deck)?.unwrap_or(srs_cli::DEFAULT_DECK);
+
+    let text = io::read_to_string(io::stdin())?;
+    let (records, errors) = import::parse_new_cards(&srs, &text)?;
+
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("line {}: {}", error.line, error.message);
+        }
+
+        return Err("no cards were added".into());
+    }
+
+    for record in &records {
+        warn_about_duplicates(&srs, &record.front, Some(record.line))?;
+    }
+
+    let new_cards: Vec<NewCard> = records
+        .into_iter()
+        .map(|r| NewCard {
+            front: r.front,
+            back: r.back,
+            deck,
+            tags: r.tags,
+        })
+        .collect();
+
+    for id in srs_cli::add_cards(srs, path, now_in_epoch_days(), &new_cards)? {
+        println!("Added card {id}.");
+    }
+
+    Ok(())
+}
+
+/// Prints a warning to stderr for each card with the same front as a new card, which is added
+/// without asking.
+fn warn_about_duplicates(srs: &Srs, front: &str, line: Option<usize>) -> Result<()> {
+    let prefix = line.map_or(String::new(), |l| format!("line {l}: "));
+
+    for duplicate in dedupe::matching(srs, front) {
+        let i = usize::from(duplicate.index);
+        let similarity = if duplicate.is_exact {
+            "the same"
+        } else {
+            "a similar"
+        };
+
+        eprintln!(
+            "{prefix}warning: card {} has {similarity} front: {}",
+            srs.ids[i],
+            srs_cli::card_front(&srs.cards[i])?.replace('\n', " "),
+        );
+    }
+
+    Ok(())
+}
+
+/// What to do with a new card which has the same front as existing ones.
+enum DuplicateAction {
+    Add,
+    /// Change the new card, and check it for duplicates again.
+    Edit,
+    Cancel,
+}
+
+/// Asks the user what to do when a new card with the given front has duplicates.
+fn check_duplicates(srs: &Srs, front: &str) -> Result<DuplicateAction> {
+    let duplicates = dedupe::matching(srs, front);
+    if duplicates.is_empty() {
+        return Ok(DuplicateAction::Add);
+    }
+
+    for duplicate in &duplicates {
+        let i = usize::from(duplicate.index);
+        let similarity = if duplicate.is_exact {
+            "the same"
+        } else {
+            "a similar"
+        };
+
+        println!(
+            "Card {} has {similarity} front: {}",
+            srs.ids[i],
+            srs_cli::card_front(&srs.cards[i])?.replace('\n', " "),
+        );
+    }
+
+    Ok(
+        match prompt::choice("Add the new card anyway?", &["Add", "Edit it", "Cancel"])? {
+            0 => DuplicateAction::Add,
+            1 => DuplicateAction::Edit,
+            _ => DuplicateAction::Cancel,
+        },
+    )
 }
 
-fn list(srs: Srs) -> Result<()> {
+fn check(path: &Path) -> Result<()> {
+    let problems = srs_cli::check(path, now_in_epoch_days())?;
+
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("byte {:>8}: {}", problem.offset, problem.description);
+    }
+
+    Err(format!(
+        "found {} problems in {}",
+        problems.len(),
+        path.to_string_lossy()
+    )
+    .into())
+}
+
+fn config(
+    srs: Srs,
+    path: &Path,
+    deck: Option<&str>,
+    changes: &SettingsArgs,
+    inherit: bool,
+) -> Result<()> {
+    let Some(deck) = deck_filter(&srs, deck)? else {
+        if inherit {
+            return Err("--inherit can only be used with --deck".into());
+        }
+
+        return config_database(srs, path, changes);
+    };
+
+    if inherit && !changes.is_empty() {
+        return Err("--inherit can't be used with other settings".into());
+    }
+
+    let own_settings = srs
+        .decks
+        .iter()
+        .find(|d| d.id == deck)
+        .and_then(|d| d.settings);
+
+    if inherit || (own_settings.is_none() && changes.is_empty()) {
+        print_settings(&srs.settings, " (from the database)");
+
+        if own_settings.is_some() {
+            srs_cli::set_deck_settings(srs, path, deck, None)?;
+        }
+
+        return Ok(());
+    }
+
+    let settings = changes.apply(own_settings.unwrap_or(srs.settings));
+
+    print_settings(&settings, "");
+
+    if !changes.is_empty() {
+        srs_cli::set_deck_settings(srs, path, deck, Some(settings))?;
+    }
+
+    Ok(())
+}
+
+fn config_database(srs: Srs, path: &Path, changes: &SettingsArgs) -> Result<()> {
+    let settings = changes.apply(srs.settings);
+
+    print_settings(&settings, "");
+
+    if !changes.is_empty() {
+        srs_cli::set_settings(srs, path, settings)?;
+    }
+
+    Ok(())
+}
+
+/// Prints each setting on its own line, followed by the suffix.
+fn print_settings(settings: &Settings, suffix: &str) {
+    let limit = |limit: Option<u16>| limit.map_or("none".to_string(), |l| l.to_string());
+
+    println!("scheduler: {}{suffix}", settings.scheduler);
+    println!("review limit: {}{suffix}", limit(settings.review_limit));
+    println!("new card limit: {}{suffix}", limit(settings.new_limit));
+    println!("overflow order: {}{suffix}", settings.overflow_order);
+    println!("load balance: {}{suffix}", settings.load_balance);
+    println!("max interval: {}{suffix}", limit(settings.max_interval));
+}
+
+fn deck(srs: Srs, path: &Path, action: &DeckAction) -> Result<()> {
+    match action {
+        DeckAction::List => {
+            println!("Cards | Deck");
+            println!("------|--------");
+            for deck in &srs.decks {
+                let count = srs.card_decks.iter().filter(|&&d| d == deck.id).count();
+                println!("{count:5} | {}", deck.name);
+            }
+        }
+        DeckAction::Create { name } => {
+            srs_cli::create_deck(srs, path, name.clone())?;
+            println!("Created deck '{name}'.");
+        }
+        DeckAction::Rename { name, new_name } => {
+            let id = srs_cli::deck_id(&srs, name)?;
+            srs_cli::rename_deck(srs, path, id, new_name.clone())?;
+            println!("Renamed deck '{name}' to '{new_name}'.");
+        }
+        DeckAction::Delete { name } => {
+            let id = srs_cli::deck_id(&srs, name)?;
+            if id == srs_cli::DEFAULT_DECK {
+                return Err("the default deck can't be deleted".into());
+            }
+
+            let count = srs.card_decks.iter().filter(|&&d| d == id).count();
+
+            if prompt::binary(format!(
+                "Are you sure you want to delete the deck '{name}'? Its {count} cards will be moved to the default deck."
+            ))? {
+                srs_cli::delete_deck(srs, path, id)?;
+                println!("... deleted.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Offers to merge each group of cards with the same front, one group at a time.
+fn dedupe(srs: Srs, path: &Path, filter_args: &FilterArgs) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+
+    // Merging changes the positions of cards, so the groups are kept as IDs.
+    let groups: Vec<Vec<CardId>> = dedupe::groups(&srs, &filter)
+        .into_iter()
+        .map(|group| group.iter().map(|&i| srs.ids[usize::from(i)]).collect())
+        .collect();
+
+    if groups.is_empty() {
+        println!("No duplicate cards found.");
+        return Ok(());
+    }
+
+    println!(
+        "Found {} groups of cards with the same front.",
+        groups.len()
+    );
+
+    let mut srs = srs;
+    for group in &groups {
+        println!("\n  ID | Interval | Card");
+        println!("-----|----------|--------");
+
+        let mut indices = Vec::with_capacity(group.len());
+        for &id in group {
+            let i = srs_cli::card_index(&srs, id)?;
+            indices.push(i);
+
+            let Card { front, back } = srs_cli::card(&srs, i)?;
+            let sched = &srs.schedule[usize::from(i)];
+            let interval = if sched.scheduled_for == 0 {
+                "relearn".to_string()
+            } else {
+                format!("{} days", sched.most_recent_interval)
+            };
+
+            println!(
+                "{id:4} | {interval:>8} | {} / {}",
+                front.replace('\n', " "),
+                back.replace('\n', " "),
+            );
+        }
+
+        let kept = dedupe::most_mature(&srs, &indices).ok_or("empty group of duplicates")?;
+        let kept_id = srs.ids[usize::from(kept)];
+
+        if prompt::binary(format!(
+            "Merge these {} cards into card {kept_id}?",
+            group.len()
+        ))? {
+            dedupe::merge(srs, path, group)?;
+            srs = srs_cli::open(path)?;
+            println!("... merged.");
+        }
+    }
+
+    Ok(())
+}
+
+fn export(srs: Srs, format: export::Format, filter_args: &FilterArgs) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+
     let stdout = io::stdout().lock();
     let mut out = BufWriter::with_capacity(128 * 1024, stdout);
 
-    writeln!(out, "  ID | Front")?;
-    writeln!(out, "-----|--------")?;
-    for (i, card) in srs.cards.iter().enumerate() {
-        let front = srs_cli::card_front(card)?.replace('\n', "\\n");
-        writeln!(out, "{i:4} | {front}")?;
+    export::write(&mut out, &srs, &filter, format)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn forecast(
+    srs: Srs,
+    days: u16,
+    chart: bool,
+    filter_args: &FilterArgs,
+    output: Output,
+) -> Result<()> {
+    const BAR_WIDTH: usize = 50;
+
+    let filter = filter(&srs, filter_args)?;
+    let now = now_in_epoch_days();
+    let forecast = srs_cli::forecast(&srs, now, days, &filter);
+
+    let stdout = io::stdout().lock();
+    let mut out = BufWriter::with_capacity(8 * 1024, stdout);
+
+    let columns = ["date", "cards"];
+    let rows: Vec<Vec<Value>> = forecast
+        .days
+        .iter()
+        .zip(now..=u16::MAX)
+        .map(|(&count, day)| vec![Value::string(&date::format(day)), Value::number(count)])
+        .collect();
+
+    match output {
+        Output::Json => {
+            return output::write_json(
+                &mut out,
+                &Value::object([
+                    ("lapsed", Value::number(forecast.lapsed)),
+                    ("overdue", Value::number(forecast.overdue)),
+                    ("days", output::objects(&columns, rows)),
+                ]),
+            );
+        }
+        Output::Tsv => return output::write_tsv(&mut out, &columns, &rows),
+        Output::Table => {}
+    }
+
+    writeln!(out, "Lapsed:  {}", forecast.lapsed)?;
+    writeln!(out, "Overdue: {}\n", forecast.overdue)?;
+
+    writeln!(out, " Date       | Cards")?;
+    writeln!(out, "------------|-------")?;
+
+    let bar_widths = forecast.bar_widths(BAR_WIDTH);
+    for ((&count, width), day) in forecast.days.iter().zip(bar_widths).zip(now..=u16::MAX) {
+        write!(out, " {} | {count:5}", date::format(day))?;
+        if chart && width > 0 {
+            write!(out, " {}", "█".repeat(width))?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+fn import(
+    srs: Srs,
+    path: &Path,
+    file: &Path,
+    format: Option<import::Format>,
+    deck: Option<&str>,
+    dry_run: bool,
+    allow_duplicates: bool,
+) -> Result<()> {
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| format!("failed to read {}: {e}", file.display()))?;
+    let format = format.unwrap_or_else(|| import::Format::detect(file, &text));
+
+    if format == import::Format::Json {
+        if deck.is_some() {
+            return Err("--deck can't be used with JSON exports, which include decks".into());
+        }
+
+        return import_json(srs, path, &text, dry_run);
+    }
+
+    let deck = deck_filter(&srs, deck)?.unwrap_or(srs_cli::DEFAULT_DECK);
+
+    let (records, mut errors) = import::parse(&text, format);
+    let records = if allow_duplicates {
+        records
+    } else {
+        skip_duplicates(&srs, records)
+    };
+    errors.extend(import::check(&srs, &records)?);
+    errors.sort_by_key(|e| e.line);
+
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("line {}: {}", error.line, error.message);
+        }
+
+        let problems = if errors.len() == 1 {
+            "problem"
+        } else {
+            "problems"
+        };
+        return Err(format!("{} {problems} found, so nothing was imported", errors.len()).into());
+    }
+
+    if records.is_empty() {
+        println!("No cards to import.");
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("Would import {} cards.", records.len());
+        return Ok(());
+    }
+
+    let new_cards: Vec<NewCard> = records
+        .into_iter()
+        .map(|r| NewCard {
+            front: r.front,
+            back: r.back,
+            deck,
+            tags: r.tags,
+        })
+        .collect();
+
+    let ids = srs_cli::add_cards(srs, path, now_in_epoch_days(), &new_cards)?;
+    println!(
+        "Imported {} cards, with IDs {} to {}.",
+        ids.len(),
+        ids[0],
+        ids[ids.len() - 1]
+    );
+
+    Ok(())
+}
+
+/// Replaces an empty database with the contents of a JSON export.
+fn import_json(srs: Srs, path: &Path, text: &str, dry_run: bool) -> Result<()> {
+    let imported = export::from_json(text)?;
+    let count = imported.cards.len();
+
+    if dry_run {
+        println!("Would import {count} cards.");
+        return Ok(());
     }
 
+    srs_cli::import_database(srs, path, imported)?;
+    println!("Imported {count} cards.");
+
     Ok(())
 }
 
-fn delete_card(srs: Srs, path: &Path, idx: CardIndex) -> Result<()> {
-    let card = srs
-        .cards
-        .get(usize::from(idx))
-        .ok_or_else(|| format!("card {idx} doesn't exist"))?;
-    let front = srs_cli::card_front(card)?;
+/// Removes the records which have the same front as an existing card, or an earlier record, and
+/// warns about each of them.
+fn skip_duplicates(srs: &Srs, records: Vec<Record>) -> Vec<Record> {
+    let mut fronts = HashSet::new();
+    let mut kept = Vec::with_capacity(records.len());
+
+    for record in records {
+        if let Some(duplicate) = dedupe::matching(srs, &record.front).first() {
+            let id = srs.ids[usize::from(duplicate.index)];
+            eprintln!(
+                "line {}: skipped, card {id} has the same front",
+                record.line
+            );
+        } else if !fronts.insert(dedupe::normalise(&record.front)) {
+            eprintln!(
+                "line {}: skipped, an earlier card in the file has the same front",
+                record.line
+            );
+        } else {
+            kept.push(record);
+        }
+    }
+
+    kept
+}
+
+/// Returns the ID of the deck with the given name, or None when no name is given.
+fn deck_filter(srs: &Srs, name: Option<&str>) -> Result<Option<DeckId>> {
+    name.map(|n| srs_cli::deck_id(srs, n)).transpose()
+}
+
+fn filter(srs: &Srs, args: &FilterArgs) -> Result<Filter> {
+    Ok(Filter {
+        deck: deck_filter(srs, args.deck.as_deref())?,
+        tags: args.tags.clone(),
+    })
+}
+
+fn list(srs: Srs, filter_args: &FilterArgs, output: Output) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+
+    let stdout = io::stdout().lock();
+    let mut out = BufWriter::with_capacity(128 * 1024, stdout);
+
+    let indices = (0..srs.cards.len() as CardIndex).filter(|&i| filter.matches(&srs, i));
+
+    if output == Output::Table {
+        writeln!(out, "  ID | Front")?;
+        writeln!(out, "-----|--------")?;
+        for i in indices {
+            let i = usize::from(i);
+            let front = srs_cli::card_front(&srs.cards[i])?.replace('\n', "\\n");
+            writeln!(out, "{:4} | {front}", srs.ids[i])?;
+        }
+
+        return Ok(());
+    }
+
+    let columns = ["id", "front", "back", "interval", "due", "tags"];
+    let mut rows = Vec::new();
+    for i in indices {
+        let Card { front, back } = srs_cli::card(&srs, i)?;
+        let i = usize::from(i);
+        let sched = &srs.schedule[i];
+
+        rows.push(vec![
+            Value::number(srs.ids[i]),
+            Value::string(&front),
+            Value::string(&back),
+            Value::number(sched.most_recent_interval),
+            due_date(sched.scheduled_for),
+            Value::Array(srs.card_tags[i].iter().map(|t| Value::string(t)).collect()),
+        ]);
+    }
+
+    write_rows(&mut out, output, &columns, rows)
+}
+
+/// Lists the cards which would be reviewed now, in the order they're stored.
+fn due(srs: Srs, filter_args: &FilterArgs, output: Output) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+
+    let stdout = io::stdout().lock();
+    let mut out = BufWriter::with_capacity(128 * 1024, stdout);
+
+    warn_about_vacation(&srs);
+    let indices = srs_cli::cards_to_review(&srs, now_in_epoch_days(), &filter);
+
+    if output == Output::Table {
+        writeln!(out, "  ID | Due        | Interval | Front")?;
+        writeln!(out, "-----|------------|----------|--------")?;
+        for i in indices {
+            let i = usize::from(i);
+            let sched = &srs.schedule[i];
+            let due = match sched.scheduled_for {
+                0 => "relearn".to_string(),
+                day => date::format(day),
+            };
+            let front = srs_cli::card_front(&srs.cards[i])?.replace('\n', "\\n");
+
+            writeln!(
+                out,
+                "{:4} | {due:10} | {:8} | {front}",
+                srs.ids[i], sched.most_recent_interval,
+            )?;
+        }
+
+        return Ok(());
+    }
+
+    let columns = ["id", "front", "interval", "due"];
+    let mut rows = Vec::with_capacity(indices.len());
+    for i in indices {
+        let i = usize::from(i);
+        let sched = &srs.schedule[i];
+
+        rows.push(vec![
+            Value::number(srs.ids[i]),
+            Value::string(srs_cli::card_front(&srs.cards[i])?),
+            Value::number(sched.most_recent_interval),
+            due_date(sched.scheduled_for),
+        ]);
+    }
+
+    write_rows(&mut out, output, &columns, rows)
+}
+
+/// Returns the day a card is scheduled for as a date, or null when it's waiting to be relearned.
+fn due_date(scheduled_for: u16) -> Value {
+    match scheduled_for {
+        0 => Value::Null,
+        day => Value::String(date::format(day)),
+    }
+}
+
+/// Writes rows in JSON or TSV. Each subcommand writes its own tables.
+fn write_rows(
+    out: &mut impl Write,
+    output: Output,
+    columns: &[&'static str],
+    rows: Vec<Vec<Value>>,
+) -> Result<()> {
+    match output {
+        Output::Json => output::write_json(out, &output::objects(columns, rows)),
+        Output::Tsv => output::write_tsv(out, columns, &rows),
+        Output::Table => Err("tables are written by each subcommand".into()),
+    }
+}
+
+fn delete_card(srs: Srs, path: &Path, id: CardId) -> Result<()> {
+    let idx = srs_cli::card_index(&srs, id)?;
+    let front = srs_cli::card_front(&srs.cards[usize::from(idx)])?;
 
     if prompt::binary(format!(
         "Are you sure you want to delete '{}'?",
         front.replace('\n', " ")
     ))? {
-        srs_cli::delete_card(srs, path, idx)?;
+        srs_cli::delete_card(srs, path, id)?;
         println!("... deleted.");
     }
 
     Ok(())
 }
 
-fn edit_card(srs: Srs, path: &Path, idx: CardIndex) -> Result<()> {
+fn move_card(srs: Srs, path: &Path, id: CardId, deck: &str) -> Result<()> {
+    let deck_id = srs_cli::deck_id(&srs, deck)?;
+
+    srs_cli::move_card(srs, path, id, deck_id)?;
+    println!("Moved card {id} to '{deck}'.");
+
+    Ok(())
+}
+
+fn edit_card(srs: Srs, path: &Path, id: CardId) -> Result<()> {
+    let idx = srs_cli::card_index(&srs, id)?;
     let Card { front, back } = srs_cli::card(&srs, idx)?;
+    let tags = &srs.card_tags[usize::from(idx)];
 
-    let (edited_front, edited_back) = open_editor(&front, &back)?;
+    let (edited_front, edited_back, edited_tags) = open_editor(&front, &back, tags)?;
 
-    srs_cli::edit_card(srs, path, idx, edited_front, edited_back)
+    srs_cli::edit_card(srs, path, id, edited_front, edited_back, edited_tags)
 }
 
-fn review(srs: Srs, path: &Path) -> Result<()> {
-    let mut card_indices = srs_cli::cards_to_review(&srs, now_in_epoch_days());
+fn restore(path: &Path, backup: Option<u8>) -> Result<()> {
+    if let Some(n) = backup {
+        srs_cli::restore(path, n)?;
+        println!("Restored backup {n}. The replaced database is now backup 1.");
+        return Ok(());
+    }
+
+    let backups = srs_cli::backups(path);
+    if backups.is_empty() {
+        println!("No backups of {} exist.", path.to_string_lossy());
+        return Ok(());
+    }
+
+    for (n, backup_path) in backups {
+        let summary = match srs_cli::open(&backup_path) {
+            Ok(srs) => format!("{} cards", srs.cards.len()),
+            Err(e) => format!("unusable: {e}"),
+        };
+        println!("{n} | {} | {summary}", backup_path.to_string_lossy());
+    }
+    println!("\nRestore one with --backup <N>.");
+
+    Ok(())
+}
+
+/// Reviews the cards which are due. Cards answered with [Grade::Again] are shown again later in
+/// the session, after `gap` other cards or at the end of the queue, until they're remembered.
+///
+/// Answers are recorded in a journal as they're given, so that quitting part way through, or
+/// being interrupted, doesn't lose them. They're saved by the next subcommand that's run.
+fn review(srs: Srs, path: &Path, gap: Option<usize>, filter_args: &FilterArgs) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+
+    warn_about_vacation(&srs);
+    let mut card_indices = srs_cli::cards_to_review(&srs, now_in_epoch_days(), &filter);
     let num_cards = card_indices.len();
 
     println!("{num_cards} cards to review");
@@ -106,31 +847,66 @@ fn review(srs: Srs, path: &Path) -> Result<()> {
     let mut rng = Rng::default();
     rng.shuffle(&mut card_indices);
 
-    let mut answers = Vec::with_capacity(card_indices.len());
+    let mut journal = Journal::create(path)?;
 
-    let mut num_correct = 0;
-    for i in card_indices {
-        let card = srs_cli::card(&srs, i)?;
+    let result = review_queue(&srs, card_indices, gap, &mut journal);
 
-        let is_correct = review_card(&card)?;
-        if is_correct {
-            num_correct += 1;
-        }
+    let count = journal.apply(path)?;
+
+    if result.is_err() && count > 0 {
+        println!("\nSaved {count} answers.");
+    }
+
+    result
+}
 
-        answers.push(Answer {
-            card_index: i,
-            is_correct,
-        });
+fn review_queue(
+    srs: &Srs,
+    card_indices: Vec<CardIndex>,
+    gap: Option<usize>,
+    journal: &mut Journal,
+) -> Result<()> {
+    let mut queue = Queue::new(card_indices, gap);
+
+    while let Some(i) = queue.next() {
+        let card = srs_cli::card(srs, i)?;
+
+        let grade = review_card(&card)?;
+        queue.answer(grade);
+
+        let answer = Answer {
+            card_id: srs.ids[usize::from(i)],
+            grade,
+        };
+        journal.append(now_in_epoch_days(), &answer)?;
 
         println!();
     }
 
-    println!("Finished review. Answered {num_correct}/{num_cards} correctly.");
+    println!(
+        "Finished review. Answered {}/{} correctly on the first try, and {}/{} answers overall.",
+        queue.num_correct_first_try(),
+        queue.num_cards(),
+        queue.num_correct(),
+        queue.num_answers(),
+    );
+
+    Ok(())
+}
+
+/// Applies the answers from a review which was interrupted before they were saved, and returns the
+/// updated database. This is done before any subcommand runs, so that none of them see the
+/// schedule from before the answers.
+fn apply_journal(srs: Srs, path: &Path) -> Result<Srs> {
+    let (srs, count) = journal::apply(srs, path)?;
+    if count > 0 {
+        eprintln!("Saved {count} answers from a review which was interrupted.");
+    }
 
-    srs_cli::apply_answers(srs, path, now_in_epoch_days(), &mut answers, &mut rng)
+    Ok(srs)
 }
 
-fn review_card(card: &Card) -> Result<bool> {
+fn review_card(card: &Card) -> Result<Grade> {
     println!("{}\n", card.front);
 
     prompt::enter("Press enter to show answer")?;
@@ -138,37 +914,312 @@ fn review_card(card: &Card) -> Result<bool> {
 
     println!("{}\n", card.back);
 
-    prompt::binary("Correct?")
+    prompt::grade("How well did you remember it?")
 }
 
-fn stats(srs: Srs) -> Result<()> {
+fn search(srs: Srs, query: &str, mode: search::Mode, filter_args: &FilterArgs) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+    let query = Query::new(query, mode)?;
+
+    let search::Results { hits, skipped } = search::search(&srs, &query, &filter)?;
+    for (i, reason) in skipped {
+        eprintln!(
+            "warning: card {} was skipped: {reason}",
+            srs.ids[usize::from(i)]
+        );
+    }
+
+    if hits.is_empty() {
+        println!("No cards found.");
+        return Ok(());
+    }
+
+    let highlight = io::stdout().is_terminal();
+
+    let stdout = io::stdout().lock();
+    let mut out = BufWriter::with_capacity(128 * 1024, stdout);
+
+    writeln!(out, "  ID | Side  | Match")?;
+    writeln!(out, "-----|-------|--------")?;
+    for hit in &hits {
+        let id = srs.ids[usize::from(hit.index)];
+        let sides = [
+            ("front", &hit.card.front, &hit.front),
+            ("back", &hit.card.back, &hit.back),
+        ];
+
+        for (side, text, range) in sides {
+            if let Some(range) = range {
+                let snippet = snippet(text, range.clone(), highlight);
+                writeln!(out, "{id:4} | {side:5} | {snippet}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Pushes the due dates of every card which isn't waiting to be relearned back by `days`.
+fn shift(srs: Srs, path: &Path, days: u16) -> Result<()> {
+    let count = srs.schedule.iter().filter(|s| s.scheduled_for != 0).count();
+
+    vacation::shift(srs, path, days)?;
+    println!("Pushed the due dates of {count} cards back by {days} days.");
+
+    Ok(())
+}
+
+/// Prints a card, including ones which can't be decoded, along with everything stored about it.
+fn show(srs: Srs, id: CardId) -> Result<()> {
+    let i = usize::from(srs_cli::card_index(&srs, id)?);
+    let bytes = &srs.cards[i];
+    let sched = &srs.schedule[i];
+
+    let separator = bytes.iter().position(|&b| b == Card::SEPARATOR);
+    let (front, back) = match separator {
+        Some(s) => (&bytes[..s], &bytes[s + 1..]),
+        None => (&bytes[..], &[][..]),
+    };
+
+    let deck = srs
+        .decks
+        .iter()
+        .find(|d| d.id == srs.card_decks[i])
+        .map_or("(missing)", |d| d.name.as_str());
+
     let stdout = io::stdout().lock();
     let mut out = BufWriter::with_capacity(8 * 1024, stdout);
 
-    writeln!(out, " Days |  ✓  |  ✕  ")?;
-    writeln!(out, "------|-----|-----")?;
-    for (i, stat) in srs.stats.iter().enumerate() {
-        writeln!(out, "  {i:3} | {:3} | {:3}", stat.correct, stat.wrong)?;
+    writeln!(out, "Card {id}")?;
+    writeln!(out, "Deck:      {deck}")?;
+    writeln!(out, "Tags:      {}", srs.card_tags[i].join(" "))?;
+    writeln!(out, "\nFront:\n{}", String::from_utf8_lossy(front))?;
+    writeln!(out, "\nBack:\n{}", String::from_utf8_lossy(back))?;
+
+    writeln!(out, "\nInterval:  {} days", sched.most_recent_interval)?;
+    if sched.scheduled_for == 0 {
+        writeln!(out, "Due:       as soon as possible")?;
+        writeln!(out, "Lapsed:    yes, it was forgotten when last answered")?;
+    } else {
+        writeln!(
+            out,
+            "Due:       {} (day {})",
+            date::format(sched.scheduled_for),
+            sched.scheduled_for
+        )?;
+        writeln!(out, "Lapsed:    no")?;
+    }
+    if limits::is_new(&srs, i as CardIndex) {
+        writeln!(out, "New:       yes, it hasn't been answered yet")?;
+    } else {
+        writeln!(out, "New:       no")?;
+    }
+    let memory = &sched.memory;
+    writeln!(
+        out,
+        "Memory:    ease {}, stability {}, difficulty {}",
+        memory.ease, memory.stability, memory.difficulty
+    )?;
+
+    let len = bytes.len();
+    match separator {
+        Some(s) => writeln!(out, "\nStored bytes: {len}, separator at byte {s}")?,
+        None => writeln!(out, "\nStored bytes: {len}, with no separator")?,
+    }
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    char::from(b)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        writeln!(out, "  {:04x}  {:47}  |{text}|", row * 16, hex.join(" "))?;
+    }
+
+    let history: Vec<_> = srs.history.iter().filter(|r| r.card_id == id).collect();
+    writeln!(out, "\nHistory: {} answers", history.len())?;
+    if !history.is_empty() {
+        writeln!(out, "  Date       | Grade | Interval")?;
+        writeln!(out, "  -----------|-------|----------")?;
+    }
+    for review in history {
+        writeln!(
+            out,
+            "  {} | {:5} | {} -> {}",
+            date::format(review.day),
+            review.grade.to_string(),
+            review.previous_interval,
+            review.new_interval,
+        )?;
     }
 
     Ok(())
 }
 
-fn open_editor(front: &str, back: &str) -> Result<(String, String)> {
-    let divider = "----------";
-    let template = format!("{front}\n{divider}\n{back}\n");
+/// Returns the part of `text` around the match at `range`, on a single line. The match is
+/// highlighted when `highlight` is true.
+fn snippet(text: &str, range: Range<usize>, highlight: bool) -> String {
+    const CONTEXT_CHARS: usize = 30;
+
+    let start = text[..range.start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT_CHARS - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[range.end..]
+        .char_indices()
+        .nth(CONTEXT_CHARS)
+        .map_or(text.len(), |(i, _)| range.end + i);
+
+    let one_line = |s: &str| s.replace('\n', "\\n");
+    let matched = one_line(&text[range.clone()]);
+
+    format!(
+        "{}{}{}{}{}",
+        if start > 0 { "…" } else { "" },
+        one_line(&text[start..range.start]),
+        if highlight {
+            format!("\x1b[1;31m{matched}\x1b[0m")
+        } else {
+            matched
+        },
+        one_line(&text[range.end..end]),
+        if end < text.len() { "…" } else { "" },
+    )
+}
+
+fn stats(srs: Srs, filter_args: &FilterArgs, output: Output) -> Result<()> {
+    let filter = filter(&srs, filter_args)?;
+
+    let stdout = io::stdout().lock();
+    let mut out = BufWriter::with_capacity(8 * 1024, stdout);
+
+    let stats = srs_cli::stats(&srs, &filter);
+
+    if output == Output::Table {
+        writeln!(out, " Days |  ✓  |  ✕  ")?;
+        writeln!(out, "------|-----|-----")?;
+        for (i, stat) in stats.iter().enumerate() {
+            writeln!(out, "  {i:3} | {:3} | {:3}", stat.correct, stat.wrong)?;
+        }
+
+        return Ok(());
+    }
+
+    // The proportion of answers which were correct, or null when there weren't any.
+    let retention = |correct: u32, wrong: u32| {
+        let total = f64::from(correct) + f64::from(wrong);
+        if total == 0.0 {
+            Value::Null
+        } else {
+            Value::Number(format!("{:.4}", f64::from(correct) / total))
+        }
+    };
+
+    let columns = ["interval", "correct", "wrong", "retention"];
+    let rows: Vec<Vec<Value>> = stats
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.correct > 0 || s.wrong > 0)
+        .map(|(interval, s)| {
+            vec![
+                Value::number(interval),
+                Value::number(s.correct),
+                Value::number(s.wrong),
+                retention(s.correct, s.wrong),
+            ]
+        })
+        .collect();
+
+    if output == Output::Tsv {
+        return output::write_tsv(&mut out, &columns, &rows);
+    }
+
+    let correct = stats.iter().map(|s| s.correct).sum();
+    let wrong = stats.iter().map(|s| s.wrong).sum();
+    let total = Value::object([
+        ("correct", Value::number(correct)),
+        ("wrong", Value::number(wrong)),
+        ("retention", retention(correct, wrong)),
+    ]);
+
+    output::write_json(
+        &mut out,
+        &Value::object([
+            ("buckets", output::objects(&columns, rows)),
+            ("total", total),
+        ]),
+    )
+}
+
+fn vacation(srs: Srs, path: &Path, action: &VacationAction) -> Result<()> {
+    let today = now_in_epoch_days();
+
+    match action {
+        VacationAction::Status => match srs.vacation_start {
+            Some(day) => println!(
+                "Vacation mode has been on since {} ({} days).",
+                date::format(day),
+                today.saturating_sub(day),
+            ),
+            None => println!("Vacation mode is off."),
+        },
+        VacationAction::Start => {
+            vacation::start(srs, path, today)?;
+            println!("Vacation mode is on. No cards are due until it's turned off.");
+        }
+        VacationAction::End => {
+            let days = vacation::end(srs, path, today)?;
+            println!("Vacation mode is off. Due dates were pushed back by {days} days.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Explains why no cards are due while vacation mode is on.
+fn warn_about_vacation(srs: &Srs) {
+    if let Some(day) = srs.vacation_start {
+        eprintln!(
+            "Vacation mode has been on since {}, so no cards are due. Turn it off with `vacation end`.",
+            date::format(day),
+        );
+    }
+}
+
+/// Lets the user edit a card, and returns its front, back, and tags. The tags are on the last line
+/// of the template, after the back.
+fn open_editor(front: &str, back: &str, tags: &[String]) -> Result<(String, String, Vec<String>)> {
+    let divider = import::DIVIDER;
+    let tags_prefix = import::TAGS_PREFIX;
+    let template = format!(
+        "{front}\n{divider}\n{back}\n\n{tags_prefix} {}\n",
+        tags.join(" ")
+    );
 
     let output = editor::edit(&template)?;
 
-    output
+    let (output, tags) = match output.trim_end().rsplit_once('\n') {
+        Some((rest, last)) if last.starts_with(tags_prefix) => {
+            (rest, tags::parse_list(&last[tags_prefix.len()..])?)
+        }
+        _ => (output.as_str(), Vec::new()),
+    };
+
+    let (front, back) = output
         .split_once(divider)
-        .ok_or_else(|| "Missing divider between front and back of card".into())
-        .map(|(front, back)| (front.trim().to_string(), back.trim().to_string()))
-        .and_then(|(front, back)| {
-            if front.is_empty() {
-                Err("Front of card can't be empty".into())
-            } else {
-                Ok((front, back))
-            }
-        })
+        .ok_or("Missing divider between front and back of card")?;
+    let (front, back) = (front.trim().to_string(), back.trim().to_string());
+
+    if front.is_empty() {
+        return Err("Front of card can't be empty".into());
+    }
+
+    Ok((front, back, tags))
 }
@@ -1,10 +1,20 @@
 //! Handling of command line arguments.
 
+use crate::output::Output;
 use srs_cli::error::Result;
+use srs_cli::export;
+use srs_cli::import;
+use srs_cli::limits::{self, OverflowOrder};
+use srs_cli::scheduler::SchedulerKind;
+use srs_cli::search;
+use srs_cli::tags;
+use srs_cli::{CardId, Settings};
 use std::env::args_os;
 use std::ffi::{OsStr, OsString};
+use std::fmt::Display;
 use std::path::PathBuf;
 use std::process;
+use std::str::FromStr;
 
 /// A parsed representation of command line arguments.
 #[derive(Debug)]
@@ -14,23 +24,246 @@ pub struct Opt {
 
     /// The path of the database file. Defaults to ./srs.db.
     pub path: PathBuf,
+
+    /// The format of the output of subcommands which list things.
+    pub output: Output,
 }
 
 /// The subcommand to run.
 #[derive(Debug)]
 pub enum Subcommand {
-    /// Create a new card.
-    Add,
-    /// List all cards.
-    List,
+    /// Create a new card in the given deck, or the default deck when none is given. The card is
+    /// written in an editor, unless it's given with `front` and `back`, or `stdin` is set to read
+    /// cards in the editor's format from stdin.
+    Add {
+        deck: Option<String>,
+        front: Option<String>,
+        back: Option<String>,
+        stdin: bool,
+    },
+    /// Scan the database for problems.
+    Check,
+    /// Change the settings of the database, or of the given deck. Only the settings which are
+    /// given are changed, and the current settings are output. `inherit` makes the deck use the
+    /// settings of the database again.
+    Config {
+        deck: Option<String>,
+        settings: SettingsArgs,
+        inherit: bool,
+    },
+    /// Manage decks.
+    Deck(DeckAction),
+    /// List the cards which match the filter and are scheduled for review today, within the daily
+    /// limits, in the order they're stored.
+    Due { filter: FilterArgs },
+    /// Find cards which match the filter and have the same front, and offer to merge them.
+    Dedupe { filter: FilterArgs },
+    /// Write the cards which match the filter to stdout in the given format.
+    Export {
+        format: export::Format,
+        filter: FilterArgs,
+    },
+    /// Add the cards in a file in the given format, or the format matching its extension. With
+    /// `dry_run`, the file is only checked for problems.
+    Import {
+        file: PathBuf,
+        format: Option<import::Format>,
+        deck: Option<String>,
+        dry_run: bool,
+        allow_duplicates: bool,
+    },
+    /// Count the cards which match the filter and are due on each of the next `days` days,
+    /// optionally with a bar chart.
+    Forecast {
+        days: u16,
+        chart: bool,
+        filter: FilterArgs,
+    },
+    /// List the cards which match the filter.
+    List { filter: FilterArgs },
+    /// Move the card with the given ID to the given deck.
+    Move { card_id: CardId, deck: String },
     /// Delete the card with the given ID.
-    Delete { card_id: u16 },
+    Delete { card_id: CardId },
     /// Edit the contents of the card with the given ID.
-    Edit { card_id: u16 },
-    /// Review cards that are scheduled for review.
-    Review,
-    /// Output statistics of reviews.
-    Stats,
+    Edit { card_id: CardId },
+    /// Restore a backup of the database. Lists the backups when no backup is given.
+    Restore { backup: Option<u8> },
+    /// Push the due date of every card back by the given number of days.
+    Shift { days: u16 },
+    /// Print everything stored about the card with the given ID.
+    Show { card_id: CardId },
+    /// Search both sides of the cards which match the filter.
+    Search {
+        query: String,
+        mode: search::Mode,
+        filter: FilterArgs,
+    },
+    /// Review cards that are scheduled for review and match the filter. Forgotten cards are shown
+    /// again after `gap` other cards, or at the end when it isn't given.
+    Review {
+        gap: Option<usize>,
+        filter: FilterArgs,
+    },
+    /// Output statistics of reviews of the cards which match the filter.
+    Stats { filter: FilterArgs },
+    /// Turn vacation mode on or off.
+    Vacation(VacationAction),
+}
+
+/// Options which restrict the cards that a subcommand applies to.
+#[derive(Debug, Default)]
+pub struct FilterArgs {
+    /// The name of the deck to include cards from.
+    pub deck: Option<String>,
+    /// The combination of `--tag` and `--no-tag`.
+    pub tags: Option<tags::Expr>,
+}
+
+impl FilterArgs {
+    fn parse(args: &Arguments) -> Result<Self> {
+        // Every expression has to match when the options are repeated.
+        let tags = args
+            .values::<tags::Expr>("--tag")?
+            .into_iter()
+            .chain(
+                args.values::<tags::Expr>("--no-tag")?
+                    .into_iter()
+                    .map(tags::Expr::negate),
+            )
+            .reduce(tags::Expr::and);
+
+        Ok(Self {
+            deck: args.opt_value("--deck")?,
+            tags,
+        })
+    }
+}
+
+/// Changes to settings given to the `config` subcommand.
+#[derive(Debug, Default)]
+pub struct SettingsArgs {
+    pub scheduler: Option<SchedulerKind>,
+    pub review_limit: Option<Limit>,
+    pub new_limit: Option<Limit>,
+    pub overflow_order: Option<OverflowOrder>,
+    pub load_balance: Option<bool>,
+    pub max_interval: Option<MaxInterval>,
+}
+
+impl SettingsArgs {
+    fn parse(args: &Arguments) -> Result<Self> {
+        Ok(Self {
+            scheduler: args.opt_value("--scheduler")?,
+            review_limit: args.opt_value("--review-limit")?,
+            new_limit: args.opt_value("--new-limit")?,
+            overflow_order: args.opt_value("--overflow-order")?,
+            load_balance: args.opt_value("--load-balance")?,
+            max_interval: args.opt_value("--max-interval")?,
+        })
+    }
+
+    /// Returns whether no settings were given.
+    pub fn is_empty(&self) -> bool {
+        self.scheduler.is_none()
+            && self.review_limit.is_none()
+            && self.new_limit.is_none()
+            && self.overflow_order.is_none()
+            && self.load_balance.is_none()
+            && self.max_interval.is_none()
+    }
+
+    /// Returns the given settings with the changes applied.
+    pub fn apply(&self, mut settings: Settings) -> Settings {
+        if let Some(scheduler) = self.scheduler {
+            settings.scheduler = scheduler;
+        }
+        if let Some(Limit(limit)) = self.review_limit {
+            settings.review_limit = limit;
+        }
+        if let Some(Limit(limit)) = self.new_limit {
+            settings.new_limit = limit;
+        }
+        if let Some(order) = self.overflow_order {
+            settings.overflow_order = order;
+        }
+        if let Some(load_balance) = self.load_balance {
+            settings.load_balance = load_balance;
+        }
+        if let Some(MaxInterval(days)) = self.max_interval {
+            settings.max_interval = days;
+        }
+
+        settings
+    }
+}
+
+/// A daily limit, or `none` for no limit.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit(pub Option<u16>);
+
+impl FromStr for Limit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_optional_number(s, 0).map(Self)
+    }
+}
+
+/// A maximum interval in days, or `none` for no maximum.
+#[derive(Clone, Copy, Debug)]
+pub struct MaxInterval(pub Option<u16>);
+
+impl FromStr for MaxInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_optional_number(s, 1).map(Self)
+    }
+}
+
+/// Parses a number from `min` up to the largest that settings can store, or `none`.
+fn parse_optional_number(s: &str, min: u16) -> std::result::Result<Option<u16>, String> {
+    if s == "none" {
+        return Ok(None);
+    }
+
+    match s.parse() {
+        Ok(n) if (min..=limits::MAX_LIMIT).contains(&n) => Ok(Some(n)),
+        _ => Err(format!(
+            "expected a number from {min} to {} or none",
+            limits::MAX_LIMIT
+        )),
+    }
+}
+
+/// The action of the `vacation` subcommand.
+#[derive(Debug)]
+pub enum VacationAction {
+    /// Print whether vacation mode is on.
+    Status,
+    /// Turn vacation mode on. No cards are due until it's turned off.
+    Start,
+    /// Turn vacation mode off, pushing due dates back by the length of the vacation.
+    End,
+}
+
+/// The action of the `deck` subcommand.
+#[derive(Debug)]
+pub enum DeckAction {
+    /// List all decks, with the number of cards in each.
+    List,
+    Create {
+        name: String,
+    },
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Delete a deck, moving its cards to the default deck.
+    Delete {
+        name: String,
+    },
 }
 
 impl Opt {
@@ -63,25 +296,127 @@ impl Opt {
             None => PathBuf::from("srs.db"),
         };
 
+        let output = args.opt_value("--output")?.unwrap_or_default();
+
         let subcommand = args
             .subcommand()
             .ok_or_else(|| "missing subcommand".to_string())?;
 
         let subcommand = match subcommand {
-            "add" => Subcommand::Add,
-            "list" => Subcommand::List,
+            "add" => {
+                let front: Option<String> = args.opt_value("--front")?;
+                let back: Option<String> = args.opt_value("--back")?;
+                let stdin = args.contains("--stdin");
+
+                if stdin && (front.is_some() || back.is_some()) {
+                    return Err("--stdin can't be used with --front or --back".into());
+                }
+                if back.is_some() && front.is_none() {
+                    return Err("--back can't be used without --front".into());
+                }
+
+                Subcommand::Add {
+                    deck: args.opt_value("--deck")?,
+                    front,
+                    back,
+                    stdin,
+                }
+            }
+            "check" => Subcommand::Check,
+            "config" => Subcommand::Config {
+                deck: args.opt_value("--deck")?,
+                settings: SettingsArgs::parse(&args)?,
+                inherit: args.contains("--inherit"),
+            },
+            "dedupe" => Subcommand::Dedupe {
+                filter: FilterArgs::parse(&args)?,
+            },
+            "deck" => Subcommand::Deck(match args.positional(1) {
+                None | Some("list") => DeckAction::List,
+                Some("create") => DeckAction::Create {
+                    name: args.required_positional(2, "name")?,
+                },
+                Some("rename") => DeckAction::Rename {
+                    name: args.required_positional(2, "name")?,
+                    new_name: args.required_positional(3, "new name")?,
+                },
+                Some("delete") => DeckAction::Delete {
+                    name: args.required_positional(2, "name")?,
+                },
+                Some(action) => return Err(format!("unknown deck action `{action}`").into()),
+            }),
+            "export" => Subcommand::Export {
+                format: args.opt_value("--format")?.unwrap_or(export::Format::Json),
+                filter: FilterArgs::parse(&args)?,
+            },
+            "forecast" => Subcommand::Forecast {
+                days: args.opt_value("--days")?.unwrap_or(30),
+                chart: args.contains("--chart"),
+                filter: FilterArgs::parse(&args)?,
+            },
+            "import" => Subcommand::Import {
+                file: PathBuf::from(args.required_positional(1, "file")?),
+                format: args.opt_value("--format")?,
+                deck: args.opt_value("--deck")?,
+                dry_run: args.contains("--dry-run"),
+                allow_duplicates: args.contains("--allow-duplicates"),
+            },
+            "due" => Subcommand::Due {
+                filter: FilterArgs::parse(&args)?,
+            },
+            "list" => Subcommand::List {
+                filter: FilterArgs::parse(&args)?,
+            },
+            "move" => Subcommand::Move {
+                card_id: args.value("--card-id")?,
+                deck: args.value("--deck")?,
+            },
             "delete" => Subcommand::Delete {
-                card_id: args.value_as_u16("--card-id")?,
+                card_id: args.value("--card-id")?,
             },
             "edit" => Subcommand::Edit {
-                card_id: args.value_as_u16("--card-id")?,
+                card_id: args.value("--card-id")?,
+            },
+            "restore" => Subcommand::Restore {
+                backup: args.opt_value("--backup")?,
             },
-            "review" => Subcommand::Review,
-            "stats" => Subcommand::Stats,
+            "review" => Subcommand::Review {
+                gap: args.opt_value("--gap")?,
+                filter: FilterArgs::parse(&args)?,
+            },
+            "search" => Subcommand::Search {
+                query: args.required_positional(1, "query")?,
+                mode: match (args.contains("--regex"), args.contains("--word")) {
+                    (false, false) => search::Mode::Substring,
+                    (false, true) => search::Mode::Word,
+                    (true, false) => search::Mode::Regex,
+                    (true, true) => return Err("--regex can't be used with --word".into()),
+                },
+                filter: FilterArgs::parse(&args)?,
+            },
+            "shift" => Subcommand::Shift {
+                days: args.value("--days")?,
+            },
+            "show" => Subcommand::Show {
+                card_id: args.value("--card-id")?,
+            },
+            "stats" => Subcommand::Stats {
+                filter: FilterArgs::parse(&args)?,
+            },
+            "vacation" => Subcommand::Vacation(match args.positional(1) {
+                None | Some("status") => VacationAction::Status,
+                Some("start") => VacationAction::Start,
+                Some("end") => VacationAction::End,
+                Some(action) => return Err(format!("unknown vacation action `{action}`").into()),
+            }),
             _ => return Err(format!("unknown subcommand `{subcommand}`").into()),
         };
 
-        Ok(Self { subcommand, path })
+        Ok(Self {
+            subcommand,
+            path,
+            output,
+        })
     }
 }
 
@@ -99,20 +434,50 @@ FLAGS:
     -V, --version    Prints version information
 
 OPTIONS:
-    -p, --path <PATH>    The path of the database file [default: srs.db]
+    -p, --path <PATH>        The path of the database file [default: srs.db]
+        --output <FORMAT>    The format of list, due, forecast and stats: table, json or tsv [default: table]
 
 SUBCOMMANDS:
     add            Create a new card
-    list           List all cards
+    check          Scan the database for corruption and unreadable cards
+    config         View or change settings, like the scheduling algorithm and daily limits
+    deck           List, create, rename or delete decks
+    dedupe         Find and merge cards with the same front
     delete         Delete a card
+    due            List the cards that are scheduled for review
     edit           Edit the contents of a card
+    export         Write cards, schedules and stats as JSON, CSV or TSV
+    forecast       Count the cards due on each of the coming days
+    import         Add cards from a CSV, TSV, text or Anki file, or restore a JSON export
+    list           List all cards
+    move           Move a card to another deck
+    restore        Restore a previous version of the database
     review         Review cards that are scheduled for review
-    stats          View statistics of reviews"#,
+    search         Search the front and back of cards
+    shift          Push every due date back by a number of days
+    show           Show a card with its schedule, stored bytes and review history
+    stats          View statistics of reviews
+    vacation       Turn vacation mode on or off, deferring due dates while it's on"#,
         name = env!("CARGO_PKG_NAME"),
         version = env!("CARGO_PKG_VERSION"),
     );
 }
 
+/// The options which don't take a value.
+const FLAGS: [&str; 11] = [
+    "-h",
+    "--help",
+    "-V",
+    "--version",
+    "--allow-duplicates",
+    "--chart",
+    "--dry-run",
+    "--inherit",
+    "--regex",
+    "--stdin",
+    "--word",
+];
+
 #[derive(Debug)]
 struct Arguments {
     args: Vec<OsString>,
@@ -129,13 +494,35 @@ impl Arguments {
         self.args.iter().any(|arg| arg == key)
     }
 
+    /// Returns the first argument which isn't an option or the value of one, so that options like
+    /// `--output` can be given before the subcommand as well as after it.
     fn subcommand(&self) -> Option<&str> {
-        let first = self.args.first()?.to_str()?;
-        if first.starts_with('-') {
-            return None;
+        self.positional(0)
+    }
+
+    /// Returns the nth argument after the subcommand which isn't an option or the value of one.
+    /// The subcommand itself is the 0th.
+    fn positional(&self, n: usize) -> Option<&str> {
+        let mut args = self.args.iter();
+        let mut positionals = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let arg = arg.to_str()?;
+            if !arg.starts_with('-') {
+                positionals.push(arg);
+            } else if !FLAGS.contains(&arg) {
+                // Skip the value of the option
+                args.next();
+            }
         }
 
-        Some(first)
+        positionals.get(n).copied()
+    }
+
+    fn required_positional(&self, n: usize, name: &'static str) -> Result<String> {
+        self.positional(n)
+            .map(str::to_string)
+            .ok_or_else(|| format!("missing argument <{name}>").into())
     }
 
     fn opt_os_str(&self, key: &'static str) -> Option<&OsStr> {
@@ -143,16 +530,96 @@ impl Arguments {
         Some(self.args.get(idx + 1)?)
     }
 
-    fn value_as_u16(&self, key: &'static str) -> Result<u16> {
-        let value_str = self
-            .opt_os_str(key)
-            .ok_or_else(|| format!("missing option '{key}'"))?;
+    fn value<T>(&self, key: &'static str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.opt_value(key)?
+            .ok_or_else(|| format!("missing option '{key}'").into())
+    }
+
+    fn opt_value<T>(&self, key: &'static str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value_str) = self.opt_os_str(key) else {
+            return Ok(None);
+        };
+
+        Ok(Some(parse_value(key, value_str)?))
+    }
+
+    /// Returns the values of every occurrence of an option which can be repeated, in order.
+    fn values<T>(&self, key: &'static str) -> Result<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.args
+            .iter()
+            .zip(self.args.iter().skip(1))
+            .filter(|(arg, _)| *arg == key)
+            .map(|(_, value)| parse_value(key, value))
+            .collect()
+    }
+}
+
+fn parse_value<T>(key: &'static str, value_str: &OsStr) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let str = value_str
+        .to_str()
+        .ok_or_else(|| format!("invalid argument for '{key}' {value_str:?}"))?;
+    str.parse::<T>()
+        .map_err(|err| format!("failed to parse value '{str}' for key '{key}': {err}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Arguments {
+        Arguments {
+            args: args.iter().map(OsString::from).collect(),
+        }
+    }
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn repeated_tag_options_all_have_to_match() {
+        let filter = FilterArgs::parse(&args(&[
+            "list", "--tag", "rust", "--tag", "go", "--no-tag", "old", "--no-tag", "draft",
+        ]))
+        .unwrap();
+        let expr = filter.tags.unwrap();
+
+        assert!(expr.matches(&tags(&["rust", "go"])));
+        assert!(!expr.matches(&tags(&["rust"])));
+        assert!(!expr.matches(&tags(&["go"])));
+        assert!(!expr.matches(&tags(&["rust", "go", "draft"])));
+        assert!(!expr.matches(&tags(&["rust", "go", "old"])));
+    }
+
+    #[test]
+    fn invalid_repeated_tags_are_errors() {
+        assert!(FilterArgs::parse(&args(&["list", "--tag", "rust", "--tag", "("])).is_err());
+    }
+
+    #[test]
+    fn options_can_come_before_the_subcommand() {
+        let args = args(&[
+            "--output", "json", "-p", "x.db", "search", "--word", "query",
+        ]);
 
-        let str = value_str
-            .to_str()
-            .ok_or_else(|| format!("invalid argument for '{key}' {value_str:?}"))?;
-        Ok(str
-            .parse::<u16>()
-            .map_err(|err| format!("failed to parse value '{str}' for key '{key}': {err}"))?)
+        assert_eq!(args.subcommand(), Some("search"));
+        assert_eq!(args.positional(1), Some("query"));
+        assert_eq!(args.positional(2), None);
     }
 }
@@ -0,0 +1,97 @@
+//! Writing the results of subcommands in the format chosen with `--output`.
+
+use srs_cli::error::Result;
+use srs_cli::json::{self, Value};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The format that subcommands which list things write their results in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Output {
+    /// Aligned columns, for people to read.
+    #[default]
+    Table,
+    /// An array with an object for each row, or an object for results which aren't a list.
+    Json,
+    /// Tab-separated columns, with a header row. Tabs, line breaks and backslashes in values are
+    /// escaped as `\t`, `\n`, `\r` and `\\`.
+    Tsv,
+}
+
+impl Output {
+    pub const ALL: [Self; 3] = [Self::Table, Self::Json, Self::Tsv];
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Table => "table",
+            Self::Json => "json",
+            Self::Tsv => "tsv",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|o| o.to_string() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|o| o.to_string()).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// Returns an array with an object for each row, with a member for each column.
+pub fn objects(columns: &[&'static str], rows: Vec<Vec<Value>>) -> Value {
+    Value::Array(
+        rows.into_iter()
+            .map(|row| Value::object(columns.iter().copied().zip(row)))
+            .collect(),
+    )
+}
+
+pub fn write_json(out: &mut impl Write, value: &Value) -> Result<()> {
+    writeln!(out, "{}", json::to_string_pretty(value))?;
+
+    Ok(())
+}
+
+/// Writes the rows with a header row of the column names. Arrays are joined with spaces, and nulls
+/// are written as empty values.
+pub fn write_tsv(
+    out: &mut impl Write,
+    columns: &[&'static str],
+    rows: &[Vec<Value>],
+) -> Result<()> {
+    writeln!(out, "{}", columns.join("\t"))?;
+
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(tsv_field).collect();
+        writeln!(out, "{}", fields.join("\t"))?;
+    }
+
+    Ok(())
+}
+
+fn tsv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.clone(),
+        Value::String(s) => s
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r"),
+        Value::Array(values) => values.iter().map(tsv_field).collect::<Vec<_>>().join(" "),
+        Value::Object(_) => json::to_string_pretty(value).replace('\n', " "),
+    }
+}
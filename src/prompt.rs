@@ -1,5 +1,6 @@
 //! Prompts displayed to the user to gather input.
 
+use crate::Grade;
 use crate::error::Result;
 use std::io::{stdin, stdout, BufRead, Write};
 
@@ -28,6 +29,64 @@ pub fn binary(prompt: impl AsRef<str>) -> Result<bool> {
     Err("No more input. Exiting instead of answering...".into())
 }
 
+/// Displays the given prompt along with the available grades, and waits for the number of one of
+/// them.
+pub fn grade(prompt: impl AsRef<str>) -> Result<Grade> {
+    let options: Vec<String> = Grade::ALL
+        .iter()
+        .map(|g| format!("{} {g}", *g as u8))
+        .collect();
+
+    let mut stdout = stdout();
+    write!(stdout, "{} [{}] ", prompt.as_ref(), options.join(" / "))?;
+    stdout.flush()?;
+
+    let stdin = stdin().lock();
+
+    for line in stdin.lines() {
+        let line = line?;
+        if line == "q" {
+            return Err("Exiting instead of answering...".into());
+        }
+
+        if let Some(grade) = line.parse().ok().and_then(Grade::from_number) {
+            return Ok(grade);
+        }
+    }
+
+    Err("No more input. Exiting instead of answering...".into())
+}
+
+/// Displays the given prompt along with numbered options, and waits for the number of one of
+/// them. Returns the index of the chosen option.
+pub fn choice(prompt: impl AsRef<str>, options: &[&str]) -> Result<usize> {
+    let numbered: Vec<String> = options
+        .iter()
+        .enumerate()
+        .map(|(i, option)| format!("{} {option}", i + 1))
+        .collect();
+
+    let mut stdout = stdout();
+    write!(stdout, "{} [{}] ", prompt.as_ref(), numbered.join(" / "))?;
+    stdout.flush()?;
+
+    let stdin = stdin().lock();
+
+    for line in stdin.lines() {
+        let line = line?;
+        if line == "q" {
+            return Err("Exiting instead of answering...".into());
+        }
+
+        match line.parse::<usize>() {
+            Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+            _ => continue,
+        }
+    }
+
+    Err("No more input. Exiting instead of answering...".into())
+}
+
 /// Displays the given prompt and waits until enter is pressed.
 pub fn enter(prompt: impl AsRef<str>) -> Result<()> {
     let mut stdout = stdout();
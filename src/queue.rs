@@ -0,0 +1,137 @@
+//! The order cards are shown in during a review session. Cards which are forgotten are put back in
+//! the queue, so they're shown again later in the same session until they're remembered.
+
+use crate::{CardIndex, Grade};
+use std::collections::{HashSet, VecDeque};
+
+pub struct Queue {
+    cards: VecDeque<CardIndex>,
+    /// The number of other cards to show before a forgotten card is shown again. None shows it
+    /// after every other card in the queue.
+    gap: Option<usize>,
+    seen: HashSet<CardIndex>,
+    num_cards: usize,
+    num_answers: usize,
+    num_correct: usize,
+    num_correct_first_try: usize,
+}
+
+impl Queue {
+    /// Creates a queue which shows the given cards in order.
+    pub fn new(cards: Vec<CardIndex>, gap: Option<usize>) -> Self {
+        Self {
+            num_cards: cards.len(),
+            seen: HashSet::with_capacity(cards.len()),
+            cards: VecDeque::from(cards),
+            gap,
+            num_answers: 0,
+            num_correct: 0,
+            num_correct_first_try: 0,
+        }
+    }
+
+    /// Returns the card to show next, or None when the session is over.
+    pub fn next(&self) -> Option<CardIndex> {
+        self.cards.front().copied()
+    }
+
+    /// Records the answer to the card returned by [Queue::next], and moves on to the next one. A
+    /// forgotten card is shown again after `gap` other cards, or at the end if there aren't that
+    /// many left.
+    pub fn answer(&mut self, grade: Grade) {
+        let Some(i) = self.cards.pop_front() else {
+            return;
+        };
+
+        let is_first_try = self.seen.insert(i);
+        self.num_answers += 1;
+        if grade.is_correct() {
+            self.num_correct += 1;
+            if is_first_try {
+                self.num_correct_first_try += 1;
+            }
+        } else {
+            let position = self.gap.unwrap_or(self.cards.len()).min(self.cards.len());
+            self.cards.insert(position, i);
+        }
+    }
+
+    /// The number of different cards in the session.
+    pub fn num_cards(&self) -> usize {
+        self.num_cards
+    }
+
+    /// The number of answers given so far, including repeated answers to forgotten cards.
+    pub fn num_answers(&self) -> usize {
+        self.num_answers
+    }
+
+    /// The number of answers given so far which were correct.
+    pub fn num_correct(&self) -> usize {
+        self.num_correct
+    }
+
+    /// The number of cards which were remembered the first time they were shown.
+    pub fn num_correct_first_try(&self) -> usize {
+        self.num_correct_first_try
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every card in the queue, and returns the order they were shown in. Each time a card
+    /// appears in `forgotten`, it's forgotten once. Every other answer is correct.
+    fn review(queue: &mut Queue, mut forgotten: Vec<CardIndex>) -> Vec<CardIndex> {
+        let mut shown = Vec::new();
+        while let Some(i) = queue.next() {
+            shown.push(i);
+
+            let grade = match forgotten.iter().position(|&f| f == i) {
+                Some(pos) => {
+                    forgotten.remove(pos);
+                    Grade::Again
+                }
+                None => Grade::Good,
+            };
+            queue.answer(grade);
+        }
+
+        shown
+    }
+
+    #[test]
+    fn forgotten_cards_are_shown_again_after_the_gap() {
+        let mut queue = Queue::new(vec![0, 1, 2, 3], Some(2));
+
+        assert_eq!(review(&mut queue, vec![0]), [0, 1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn forgotten_cards_go_to_the_end_without_a_gap() {
+        let mut queue = Queue::new(vec![0, 1, 2], None);
+
+        assert_eq!(review(&mut queue, vec![0, 1]), [0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn gaps_past_the_end_show_cards_last() {
+        let mut queue = Queue::new(vec![0, 1], Some(10));
+
+        assert_eq!(review(&mut queue, vec![0]), [0, 1, 0]);
+    }
+
+    #[test]
+    fn counts_first_try_and_overall_answers() {
+        let mut queue = Queue::new(vec![0, 1, 2], Some(0));
+
+        let shown = review(&mut queue, vec![0, 0, 1]);
+
+        assert_eq!(shown, [0, 0, 0, 1, 1, 2]);
+        assert_eq!(queue.num_cards(), 3);
+        assert_eq!(queue.num_answers(), 6);
+        assert_eq!(queue.num_correct(), 3);
+        assert_eq!(queue.num_correct_first_try(), 1);
+    }
+}
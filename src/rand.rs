@@ -28,7 +28,7 @@ impl Rng {
     }
 
     pub fn bool(&mut self) -> bool {
-        self.next() % 2 != 0
+        !self.next().is_multiple_of(2)
     }
 
     pub fn u16(&mut self, max_inclusive: u16) -> u16 {
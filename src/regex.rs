@@ -0,0 +1,570 @@
+//! A small backtracking regular expression engine, used by [crate::search].
+//!
+//! Supported syntax:
+//!
+//! - literals, with `\` escaping any of `\.+*?()|[]{}^$`
+//! - `.` for any character except a newline
+//! - character classes like `[a-z_]` and `[^0-9]`, and the shorthands `\d`, `\w`, `\s` and their
+//!   negations `\D`, `\W`, `\S`
+//! - `^` and `$` for the start and end of a line, and `\b` for a word boundary
+//! - grouping with `(...)` or `(?:...)`, and alternation with `|`
+//! - the quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`, which are lazy when followed by `?`
+//!
+//! Word characters, for `\w` and `\b`, are the ones [is_word_char] accepts, which include letters
+//! and digits in every script. Whole-word searches use the same definition.
+
+use crate::error::Result;
+use std::cell::Cell;
+use std::ops::Range;
+
+/// The most steps that matching may take when searching a piece of text. Some patterns take
+/// exponential time to fail with a backtracking engine, so this bounds how long a search can take.
+const MAX_STEPS: usize = 1_000_000;
+
+/// The largest count allowed in `{n,m}` quantifiers.
+const MAX_REPEAT: u32 = 1000;
+
+#[derive(Debug)]
+pub struct Regex {
+    node: Node,
+    ignore_case: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    /// Any character except a newline.
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Default)]
+struct Class {
+    /// Inclusive ranges of characters.
+    ranges: Vec<(char, char)>,
+    /// Whether the class includes every word character, for `\w`.
+    word: bool,
+    negated: bool,
+}
+
+impl Class {
+    fn digit() -> Self {
+        Self {
+            ranges: vec![('0', '9')],
+            ..Default::default()
+        }
+    }
+
+    fn word() -> Self {
+        Self {
+            word: true,
+            ..Default::default()
+        }
+    }
+
+    fn space() -> Self {
+        Self {
+            ranges: vec![(' ', ' '), ('\t', '\r')],
+            ..Default::default()
+        }
+    }
+
+    fn negate(self) -> Self {
+        Self {
+            negated: !self.negated,
+            ..self
+        }
+    }
+
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let in_ranges = |c: char| {
+            (self.word && is_word_char(c)) || self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+        };
+
+        let is_match = if ignore_case {
+            in_ranges(c) || in_ranges(fold(c)) || c.to_uppercase().any(in_ranges)
+        } else {
+            in_ranges(c)
+        };
+
+        is_match != self.negated
+    }
+}
+
+/// Returns the lowercase form of a character for case-insensitive comparisons.
+pub(crate) fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Returns whether a character is part of a word: a letter or digit in any script, or `_`.
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+
+        let node = parser.alt()?;
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected '{c}' at position {} of regex", parser.pos).into());
+        }
+
+        Ok(Self { node, ignore_case })
+    }
+
+    /// Returns the leftmost match in `text` which starts at or after `start`, as a range of
+    /// character indices.
+    pub fn find_at(&self, text: &[char], start: usize) -> Result<Option<Range<usize>>> {
+        let matcher = Matcher {
+            text,
+            ignore_case: self.ignore_case,
+            steps: Cell::new(0),
+        };
+
+        for pos in start..=text.len() {
+            let mut end = None;
+            let is_match = matcher.matches(&self.node, pos, &mut |e| {
+                end = Some(e);
+                true
+            });
+
+            if matcher.steps.get() > MAX_STEPS {
+                return Err("regex is too complex to search with".into());
+            }
+
+            if is_match {
+                return Ok(end.map(|end| pos..end));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn accept(&mut self, c: char) -> bool {
+        let is_match = self.peek() == Some(c);
+        if is_match {
+            self.pos += 1;
+        }
+
+        is_match
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = self.peek().ok_or("unexpected end of regex")?;
+        self.pos += 1;
+
+        Ok(c)
+    }
+
+    fn alt(&mut self) -> Result<Node> {
+        let mut alts = vec![self.concat()?];
+        while self.accept('|') {
+            alts.push(self.concat()?);
+        }
+
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.repeat()?);
+        }
+
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repeat(&mut self) -> Result<Node> {
+        let position = self.pos;
+        let mut node = self.atom()?;
+
+        loop {
+            let (min, max) = if self.accept('*') {
+                (0, None)
+            } else if self.accept('+') {
+                (1, None)
+            } else if self.accept('?') {
+                (0, Some(1))
+            } else if self.accept('{') {
+                self.counts()?
+            } else {
+                break;
+            };
+
+            if matches!(node, Node::LineStart | Node::LineEnd | Node::WordBoundary) {
+                return Err(format!("nothing to repeat at position {position} of regex").into());
+            }
+
+            let greedy = !self.accept('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+
+        Ok(node)
+    }
+
+    /// Parses the rest of a `{n}`, `{n,}` or `{n,m}` quantifier, after the `{`.
+    fn counts(&mut self) -> Result<(u32, Option<u32>)> {
+        let min = self.number()?;
+        let max = if self.accept(',') {
+            if self.peek() == Some('}') {
+                None
+            } else {
+                Some(self.number()?)
+            }
+        } else {
+            Some(min)
+        };
+
+        if !self.accept('}') {
+            return Err(format!("expected '}}' at position {} of regex", self.pos).into());
+        }
+
+        if let Some(max) = max
+            && max < min
+        {
+            return Err(format!("invalid repeat count {{{min},{max}}}").into());
+        }
+
+        Ok((min, max))
+    }
+
+    fn number(&mut self) -> Result<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        match digits.parse() {
+            Ok(n) if n <= MAX_REPEAT => Ok(n),
+            Ok(_) => Err(format!("repeat count can't be more than {MAX_REPEAT}").into()),
+            Err(_) => Err(format!("expected a number at position {start} of regex").into()),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let position = self.pos;
+        let c = self.next()?;
+
+        let node = match c {
+            '(' => {
+                if self.accept('?') && !self.accept(':') {
+                    return Err(format!("unsupported group at position {position} of regex").into());
+                }
+
+                let node = self.alt()?;
+                if !self.accept(')') {
+                    return Err(format!("unclosed group at position {position} of regex").into());
+                }
+                node
+            }
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '[' => Node::Class(self.class()?),
+            '\\' => match self.escape()? {
+                Escape::Char(c) => Node::Char(c),
+                Escape::Class(class) => Node::Class(class),
+                Escape::WordBoundary => Node::WordBoundary,
+            },
+            '*' | '+' | '?' | '{' => {
+                return Err(format!("nothing to repeat at position {position} of regex").into());
+            }
+            c => Node::Char(c),
+        };
+
+        Ok(node)
+    }
+
+    /// Parses the rest of a character class, after the `[`.
+    fn class(&mut self) -> Result<Class> {
+        let start = self.pos - 1;
+        let mut class = Class {
+            negated: self.accept('^'),
+            ..Default::default()
+        };
+
+        let mut is_first = true;
+        loop {
+            let c = self
+                .next()
+                .map_err(|_| format!("unclosed character class at position {start} of regex"))?;
+
+            let lo = match c {
+                ']' if !is_first => break,
+                '\\' => match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Class(c) if !c.negated => {
+                        class.ranges.extend(c.ranges);
+                        class.word |= c.word;
+                        is_first = false;
+                        continue;
+                    }
+                    _ => {
+                        return Err(format!(
+                            "unsupported escape in character class at position {} of regex",
+                            self.pos - 2,
+                        )
+                        .into());
+                    }
+                },
+                c => c,
+            };
+            is_first = false;
+
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']');
+            if !is_range {
+                class.ranges.push((lo, lo));
+                continue;
+            }
+            self.pos += 1;
+
+            let hi = match self.next()? {
+                '\\' => match self.escape()? {
+                    Escape::Char(c) => c,
+                    _ => return Err("invalid range in character class".into()),
+                },
+                c => c,
+            };
+            if hi < lo {
+                return Err(format!("invalid range {lo}-{hi} in character class").into());
+            }
+            class.ranges.push((lo, hi));
+        }
+
+        Ok(class)
+    }
+
+    /// Parses the rest of an escape sequence, after the `\`.
+    fn escape(&mut self) -> Result<Escape> {
+        let escape = match self.next()? {
+            'd' => Escape::Class(Class::digit()),
+            'D' => Escape::Class(Class::digit().negate()),
+            'w' => Escape::Class(Class::word()),
+            'W' => Escape::Class(Class::word().negate()),
+            's' => Escape::Class(Class::space()),
+            'S' => Escape::Class(Class::space().negate()),
+            'b' => Escape::WordBoundary,
+            'n' => Escape::Char('\n'),
+            't' => Escape::Char('\t'),
+            c if c.is_ascii_punctuation() => Escape::Char(c),
+            c => return Err(format!("unsupported escape \\{c} in regex").into()),
+        };
+
+        Ok(escape)
+    }
+}
+
+enum Escape {
+    Char(char),
+    Class(Class),
+    WordBoundary,
+}
+
+struct Matcher<'a> {
+    text: &'a [char],
+    ignore_case: bool,
+    steps: Cell<usize>,
+}
+
+impl Matcher<'_> {
+    /// Returns whether `node` matches at `pos` with the continuation `k` succeeding on the
+    /// position where the match of `node` ends.
+    fn matches(&self, node: &Node, pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if steps > MAX_STEPS {
+            return false;
+        }
+
+        let text = self.text;
+        let current = text.get(pos).copied();
+
+        match node {
+            Node::Char(c) => {
+                let is_match = match current {
+                    Some(t) if self.ignore_case => fold(t) == fold(*c),
+                    Some(t) => t == *c,
+                    None => false,
+                };
+                is_match && k(pos + 1)
+            }
+            Node::Any => current.is_some_and(|c| c != '\n') && k(pos + 1),
+            Node::Class(class) => {
+                current.is_some_and(|c| class.matches(c, self.ignore_case)) && k(pos + 1)
+            }
+            Node::LineStart => (pos == 0 || text[pos - 1] == '\n') && k(pos),
+            Node::LineEnd => current.is_none_or(|c| c == '\n') && k(pos),
+            Node::WordBoundary => {
+                let before = pos > 0 && is_word_char(text[pos - 1]);
+                let after = current.is_some_and(is_word_char);
+                before != after && k(pos)
+            }
+            Node::Concat(nodes) => self.concat(nodes, pos, k),
+            Node::Alt(alts) => alts.iter().any(|alt| self.matches(alt, pos, k)),
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.repeat(node, *min, *max, *greedy, 0, pos, k),
+        }
+    }
+
+    fn concat(&self, nodes: &[Node], pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+        match nodes.split_first() {
+            None => k(pos),
+            Some((first, rest)) => self.matches(first, pos, &mut |p| self.concat(rest, p, k)),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+        count: u32,
+        pos: usize,
+        k: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        let can_stop = count >= min;
+        let can_continue = max.is_none_or(|max| count < max);
+
+        if can_stop && !greedy && k(pos) {
+            return true;
+        }
+
+        // Iterations which match nothing would repeat forever, so they only count towards the
+        // minimum.
+        if can_continue
+            && self.matches(node, pos, &mut |p| {
+                (p != pos || count < min) && self.repeat(node, min, max, greedy, count + 1, p, k)
+            })
+        {
+            return true;
+        }
+
+        can_stop && greedy && k(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        let text: Vec<char> = text.chars().collect();
+        let regex = Regex::new(pattern, false).unwrap();
+
+        regex
+            .find_at(&text, 0)
+            .unwrap()
+            .map(|range| text[range].iter().collect())
+    }
+
+    #[test]
+    fn literals_and_wildcards() {
+        assert_eq!(find("b.d", "abcde").as_deref(), Some("bcd"));
+        assert_eq!(find("a\\.b", "axb a.b").as_deref(), Some("a.b"));
+        assert_eq!(find("x", "abc"), None);
+        assert_eq!(find("", "abc").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert_eq!(find("ab*c", "ac abbc").as_deref(), Some("ac"));
+        assert_eq!(find("ab+c", "ac abbc").as_deref(), Some("abbc"));
+        assert_eq!(find("colou?r", "color").as_deref(), Some("color"));
+        assert_eq!(find("a{2,3}", "a aaaa").as_deref(), Some("aaa"));
+        assert_eq!(find("a{2}", "aaaa").as_deref(), Some("aa"));
+        assert_eq!(find("<.+>", "<a><b>").as_deref(), Some("<a><b>"));
+        assert_eq!(find("<.+?>", "<a><b>").as_deref(), Some("<a>"));
+        assert_eq!(find("(a*)*b", "aab").as_deref(), Some("aab"));
+    }
+
+    #[test]
+    fn classes_groups_and_anchors() {
+        assert_eq!(find("[0-9]+", "abc 123").as_deref(), Some("123"));
+        assert_eq!(find("[^a-c ]+", "abc def").as_deref(), Some("def"));
+        assert_eq!(
+            find("\\d+\\s\\w+", "x 42 apples").as_deref(),
+            Some("42 apples")
+        );
+        assert_eq!(find("gr(a|e)y", "grey").as_deref(), Some("grey"));
+        assert_eq!(find("^b", "ab\nbc").as_deref(), Some("b"));
+        assert_eq!(find("a$", "ba\nc").as_deref(), Some("a"));
+        assert_eq!(find("\\bcat\\b", "concat cat").as_deref(), Some("cat"));
+        assert_eq!(find("[]a]+", "x]a]").as_deref(), Some("]a]"));
+    }
+
+    #[test]
+    fn word_characters_include_every_script() {
+        assert_eq!(find("\\w+", "-- café_1 --").as_deref(), Some("café_1"));
+        assert_eq!(find("[\\w-]+", "(日本-語)").as_deref(), Some("日本-語"));
+        assert_eq!(find("\\W+", "日本, 語").as_deref(), Some(", "));
+        assert_eq!(find("\\bé\\b", "café é").as_deref(), Some("é"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let text: Vec<char> = "Hello WORLD".chars().collect();
+        let regex = Regex::new("w[a-z]+", true).unwrap();
+
+        assert_eq!(regex.find_at(&text, 0).unwrap(), Some(6..11));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in ["(a", "a)", "[a", "*a", "a{3,1}", "\\q", "(?=a)", "a{2000}"] {
+            assert!(Regex::new(pattern, false).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn catastrophic_backtracking_is_bounded() {
+        let text: Vec<char> = "a".repeat(40).chars().collect();
+        let regex = Regex::new("(a|aa)*c", false).unwrap();
+
+        assert!(regex.find_at(&text, 0).is_err());
+    }
+}
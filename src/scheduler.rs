@@ -0,0 +1,393 @@
+//! Algorithms which decide when cards should next be reviewed.
+
+use crate::{CardSchedule, Grade};
+use std::fmt;
+use std::str::FromStr;
+
+/// The reduction factor applied to the next interval when the card was answered incorrectly.
+const WRONG_ANSWER_PENALTY: f32 = 0.7;
+
+/// The factor applied to the growth of the interval when a card is answered with [Grade::Hard].
+const HARD_FACTOR: f32 = 0.5;
+
+/// The factor applied to the interval when a card is answered with [Grade::Easy].
+const EASY_BONUS: f32 = 1.3;
+
+/// Decides how the schedule of a card changes when it's answered.
+pub trait Scheduler {
+    /// Returns the outcome of answering the card with the given schedule. `elapsed_days` is the
+    /// number of days since the card was last answered.
+    fn next(&self, sched: &CardSchedule, grade: Grade, elapsed_days: u16) -> Outcome;
+}
+
+/// The result of answering a card.
+#[derive(Clone, Copy, Debug)]
+pub struct Outcome {
+    /// The number of days until the card should be reviewed again, before fuzz is applied. None
+    /// when the card was forgotten, and should be reviewed again as soon as possible.
+    pub interval: Option<u16>,
+    pub memory: Memory,
+}
+
+/// The state that schedulers keep for each card, in addition to its interval. Each field is only
+/// used by some schedulers, and is 0 until the card is answered with one of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Memory {
+    /// The SM-2 ease factor, in thousandths.
+    pub ease: u16,
+    /// The FSRS stability, in days.
+    pub stability: f32,
+    /// The FSRS difficulty, from 1 to 10.
+    pub difficulty: f32,
+}
+
+/// Identifies one of the available [Scheduler]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchedulerKind {
+    #[default]
+    Multiplier,
+    Sm2,
+    Fsrs,
+}
+
+impl SchedulerKind {
+    pub const ALL: [Self; 3] = [Self::Multiplier, Self::Sm2, Self::Fsrs];
+
+    pub fn scheduler(self) -> &'static dyn Scheduler {
+        match self {
+            Self::Multiplier => &Multiplier,
+            Self::Sm2 => &Sm2,
+            Self::Fsrs => &Fsrs,
+        }
+    }
+
+    /// The value used to store this in the database.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Multiplier => 0,
+            Self::Sm2 => 1,
+            Self::Fsrs => 2,
+        }
+    }
+
+    pub fn from_byte(b: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.to_byte() == b)
+    }
+}
+
+impl fmt::Display for SchedulerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Multiplier => "multiplier",
+            Self::Sm2 => "sm2",
+            Self::Fsrs => "fsrs",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for SchedulerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.to_string() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|k| k.to_string()).collect();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// Multiplies the interval by 5 on each correct answer. The first correct answer after a wrong
+/// one reduces the interval instead. Hard answers grow the interval by less, and easy answers get
+/// a bonus.
+pub struct Multiplier;
+
+impl Multiplier {
+    const GROWTH: f32 = 5.0;
+}
+
+impl Scheduler for Multiplier {
+    fn next(&self, sched: &CardSchedule, grade: Grade, _elapsed_days: u16) -> Outcome {
+        let last_was_correct = sched.scheduled_for != 0;
+
+        let factor = match grade {
+            Grade::Again => {
+                return Outcome {
+                    interval: None,
+                    memory: sched.memory,
+                };
+            }
+            Grade::Hard if last_was_correct => 1.0 + (Self::GROWTH - 1.0) * HARD_FACTOR,
+            Grade::Hard => WRONG_ANSWER_PENALTY * HARD_FACTOR,
+            Grade::Good if last_was_correct => Self::GROWTH,
+            Grade::Good => WRONG_ANSWER_PENALTY,
+            Grade::Easy if last_was_correct => Self::GROWTH * EASY_BONUS,
+            Grade::Easy => WRONG_ANSWER_PENALTY * EASY_BONUS,
+        };
+
+        // Short intervals scaled down by a penalty would round to 0 days.
+        let interval = ((sched.most_recent_interval as f32) * factor)
+            .round()
+            .max(1.0) as u16;
+
+        Outcome {
+            interval: Some(interval),
+            memory: sched.memory,
+        }
+    }
+}
+
+/// The SuperMemo 2 algorithm, which multiplies the interval by a per-card ease factor. The ease
+/// factor goes down each time the card is forgotten, and the card starts over with a 1 day
+/// interval.
+pub struct Sm2;
+
+impl Sm2 {
+    const INITIAL_EASE: u16 = 2500;
+    const MIN_EASE: u16 = 1300;
+}
+
+impl Scheduler for Sm2 {
+    fn next(&self, sched: &CardSchedule, grade: Grade, _elapsed_days: u16) -> Outcome {
+        let ease = match sched.memory.ease {
+            0 => Self::INITIAL_EASE,
+            e => e,
+        };
+
+        // SM-2 grades answers from 0 to 5, where anything below 3 is a failure. A good answer is
+        // a 4, which leaves the ease unchanged.
+        let quality: i32 = match grade {
+            Grade::Again => 2,
+            Grade::Hard => 3,
+            Grade::Good => 4,
+            Grade::Easy => 5,
+        };
+        let shortfall = 5 - quality;
+        let ease_delta = 100 - shortfall * (80 + shortfall * 20);
+        let ease = (i32::from(ease) + ease_delta).max(i32::from(Self::MIN_EASE)) as u16;
+
+        let memory = Memory {
+            ease,
+            ..sched.memory
+        };
+
+        if !grade.is_correct() {
+            return Outcome {
+                interval: None,
+                memory,
+            };
+        }
+
+        let interval = if sched.scheduled_for == 0 {
+            // Relearning after being forgotten
+            1
+        } else if sched.most_recent_interval <= 1 {
+            6
+        } else {
+            (f32::from(sched.most_recent_interval) * f32::from(ease) / 1000.0).round() as u16
+        };
+
+        Outcome {
+            interval: Some(interval),
+            memory,
+        }
+    }
+}
+
+/// The Free Spaced Repetition Scheduler (version 4.5), which models the stability and difficulty
+/// of each card to schedule it when the probability of recalling it falls to 90%.
+pub struct Fsrs;
+
+impl Fsrs {
+    /// The default parameters of FSRS 4.5.
+    const W: [f32; 17] = [
+        0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461,
+        2.1072, 0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+    ];
+    const DECAY: f32 = -0.5;
+    const FACTOR: f32 = 19.0 / 81.0;
+
+    const GOOD: f32 = 3.0;
+
+    fn retrievability(elapsed_days: u16, stability: f32) -> f32 {
+        (1.0 + Self::FACTOR * f32::from(elapsed_days) / stability).powf(Self::DECAY)
+    }
+
+    fn initial_difficulty(grade: f32) -> f32 {
+        (Self::W[4] - (grade - 3.0) * Self::W[5]).clamp(1.0, 10.0)
+    }
+}
+
+impl Scheduler for Fsrs {
+    fn next(&self, sched: &CardSchedule, grade: Grade, elapsed_days: u16) -> Outcome {
+        let w = Self::W;
+        let is_correct = grade.is_correct();
+
+        // Cards which haven't been answered with FSRS before start from their current interval,
+        // which is when they were expected to be recalled.
+        let (stability, difficulty) = if sched.memory.stability > 0.0 {
+            (sched.memory.stability, sched.memory.difficulty)
+        } else {
+            (
+                f32::from(sched.most_recent_interval.max(1)),
+                Self::initial_difficulty(Self::GOOD),
+            )
+        };
+
+        let r = Self::retrievability(elapsed_days, stability);
+
+        let stability = if is_correct {
+            let hard_penalty = if grade == Grade::Hard { w[15] } else { 1.0 };
+            let easy_bonus = if grade == Grade::Easy { w[16] } else { 1.0 };
+
+            stability
+                * (w[8].exp()
+                    * (11.0 - difficulty)
+                    * stability.powf(-w[9])
+                    * ((w[10] * (1.0 - r)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus
+                    + 1.0)
+        } else {
+            let forgotten = w[11]
+                * difficulty.powf(-w[12])
+                * ((stability + 1.0).powf(w[13]) - 1.0)
+                * (w[14] * (1.0 - r)).exp();
+            forgotten.min(stability)
+        };
+
+        let difficulty = difficulty - w[6] * (f32::from(grade as u8) - 3.0);
+        let difficulty = (w[7] * Self::initial_difficulty(Self::GOOD) + (1.0 - w[7]) * difficulty)
+            .clamp(1.0, 10.0);
+
+        let memory = Memory {
+            stability,
+            difficulty,
+            ..sched.memory
+        };
+
+        // With a desired retention of 90%, the interval is the same as the stability.
+        let interval = is_correct.then(|| stability.round().clamp(1.0, f32::from(u16::MAX)) as u16);
+
+        Outcome { interval, memory }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sched(most_recent_interval: u16, scheduled_for: u16) -> CardSchedule {
+        CardSchedule {
+            most_recent_interval,
+            scheduled_for,
+            memory: Memory::default(),
+        }
+    }
+
+    #[test]
+    fn multiplier() {
+        let outcome = Multiplier.next(&sched(4, 100), Grade::Good, 4);
+        assert_eq!(outcome.interval, Some(20));
+
+        let outcome = Multiplier.next(&sched(20, 0), Grade::Good, 20);
+        assert_eq!(outcome.interval, Some(14));
+
+        let outcome = Multiplier.next(&sched(20, 100), Grade::Again, 20);
+        assert_eq!(outcome.interval, None);
+
+        let outcome = Multiplier.next(&sched(4, 100), Grade::Hard, 4);
+        assert_eq!(outcome.interval, Some(12));
+
+        let outcome = Multiplier.next(&sched(4, 100), Grade::Easy, 4);
+        assert_eq!(outcome.interval, Some(26));
+    }
+
+    #[test]
+    fn short_intervals_after_a_lapse_stay_at_least_a_day() {
+        for interval in [1, 2] {
+            for grade in [Grade::Hard, Grade::Good] {
+                let outcome = Multiplier.next(&sched(interval, 0), grade, interval);
+                assert_eq!(outcome.interval, Some(1), "{interval} {grade}");
+            }
+        }
+
+        let outcome = Multiplier.next(&sched(3, 0), Grade::Hard, 3);
+        assert_eq!(outcome.interval, Some(1));
+    }
+
+    #[test]
+    fn large_intervals_saturate() {
+        let outcome = Multiplier.next(&sched(60_000, 100), Grade::Easy, 60_000);
+        assert_eq!(outcome.interval, Some(u16::MAX));
+
+        let card = CardSchedule {
+            memory: Memory {
+                ease: 2500,
+                ..Memory::default()
+            },
+            ..sched(60_000, 100)
+        };
+        let outcome = Sm2.next(&card, Grade::Good, 60_000);
+        assert_eq!(outcome.interval, Some(u16::MAX));
+
+        let card = CardSchedule {
+            memory: Memory {
+                stability: 60_000.0,
+                difficulty: 1.0,
+                ..Memory::default()
+            },
+            ..sched(60_000, 100)
+        };
+        let outcome = Fsrs.next(&card, Grade::Easy, 60_000);
+        assert_eq!(outcome.interval, Some(u16::MAX));
+    }
+
+    #[test]
+    fn sm2() {
+        let outcome = Sm2.next(&sched(1, 100), Grade::Good, 1);
+        assert_eq!(outcome.interval, Some(6));
+        assert_eq!(outcome.memory.ease, 2500);
+
+        let card = CardSchedule {
+            memory: outcome.memory,
+            ..sched(6, 106)
+        };
+        let outcome = Sm2.next(&card, Grade::Good, 6);
+        assert_eq!(outcome.interval, Some(15));
+
+        let card = CardSchedule {
+            memory: outcome.memory,
+            ..sched(15, 121)
+        };
+        let outcome = Sm2.next(&card, Grade::Again, 15);
+        assert_eq!(outcome.interval, None);
+        assert_eq!(outcome.memory.ease, 2180);
+    }
+
+    #[test]
+    fn fsrs() {
+        let outcome = Fsrs.next(&sched(10, 100), Grade::Good, 10);
+        let interval = outcome.interval.unwrap();
+        assert!(interval > 10, "{interval}");
+        assert!(outcome.memory.stability > 10.0);
+
+        let card = CardSchedule {
+            memory: outcome.memory,
+            ..sched(interval, 100 + interval)
+        };
+        let forgotten = Fsrs.next(&card, Grade::Again, interval);
+        assert_eq!(forgotten.interval, None);
+        assert!(forgotten.memory.stability < outcome.memory.stability);
+        assert!(forgotten.memory.difficulty > outcome.memory.difficulty);
+
+        let hard = Fsrs.next(&card, Grade::Hard, interval).interval.unwrap();
+        let good = Fsrs.next(&card, Grade::Good, interval).interval.unwrap();
+        let easy = Fsrs.next(&card, Grade::Easy, interval).interval.unwrap();
+        assert!(hard < good && good < easy, "{hard} {good} {easy}");
+    }
+}
@@ -0,0 +1,237 @@
+//! Finding cards by the text on either side of them. Matching ignores case in every [Mode].
+
+use crate::error::Result;
+use crate::regex::{self, Regex};
+use crate::{Card, CardIndex, Filter, Srs};
+use std::ops::Range;
+
+/// How a query is matched against the text of cards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// The query appears anywhere in the text.
+    #[default]
+    Substring,
+    /// The query appears without letters, digits or underscores directly before or after it.
+    Word,
+    /// The query is a regular expression. See [crate::regex] for the supported syntax.
+    Regex,
+}
+
+pub struct Query {
+    matcher: Matcher,
+}
+
+enum Matcher {
+    Literal { chars: Vec<char>, whole_word: bool },
+    Regex(Regex),
+}
+
+impl Query {
+    pub fn new(query: &str, mode: Mode) -> Result<Self> {
+        let matcher = match mode {
+            Mode::Substring | Mode::Word => {
+                if query.is_empty() {
+                    return Err("search query can't be empty".into());
+                }
+
+                Matcher::Literal {
+                    chars: query.chars().map(regex::fold).collect(),
+                    whole_word: mode == Mode::Word,
+                }
+            }
+            Mode::Regex => Matcher::Regex(Regex::new(query, true)?),
+        };
+
+        Ok(Self { matcher })
+    }
+
+    /// Returns the byte range of the first match in the given text.
+    pub fn find(&self, text: &str) -> Result<Option<Range<usize>>> {
+        let chars: Vec<char> = text.chars().collect();
+
+        let range = match &self.matcher {
+            Matcher::Literal {
+                chars: query,
+                whole_word,
+            } => find_literal(&chars, query, *whole_word),
+            Matcher::Regex(regex) => regex.find_at(&chars, 0)?,
+        };
+
+        let Some(range) = range else {
+            return Ok(None);
+        };
+
+        let byte_offset = |char_idx: usize| {
+            text.char_indices()
+                .nth(char_idx)
+                .map_or(text.len(), |(i, _)| i)
+        };
+
+        Ok(Some(byte_offset(range.start)..byte_offset(range.end)))
+    }
+}
+
+fn find_literal(text: &[char], query: &[char], whole_word: bool) -> Option<Range<usize>> {
+    if query.len() > text.len() {
+        return None;
+    }
+
+    (0..=text.len() - query.len())
+        .map(|start| start..start + query.len())
+        .find(|range| {
+            let is_match = text[range.clone()]
+                .iter()
+                .zip(query)
+                .all(|(&t, &q)| regex::fold(t) == q);
+
+            let is_word = || {
+                let before = range.start.checked_sub(1).map(|i| text[i]);
+                let after = text.get(range.end).copied();
+
+                !before.is_some_and(regex::is_word_char) && !after.is_some_and(regex::is_word_char)
+            };
+
+            is_match && (!whole_word || is_word())
+        })
+}
+
+/// A card which matched a query, with where the query matched on each side.
+pub struct Hit {
+    pub index: CardIndex,
+    pub card: Card,
+    pub front: Option<Range<usize>>,
+    pub back: Option<Range<usize>>,
+}
+
+/// The cards found by [search].
+pub struct Results {
+    pub hits: Vec<Hit>,
+    /// Cards which couldn't be searched, along with why. Either they can't be decoded, or matching
+    /// the regex against them took too long.
+    pub skipped: Vec<(CardIndex, String)>,
+}
+
+/// Returns the cards which match the filter and have a match for the query on either side, in the
+/// order they're stored.
+pub fn search(srs: &Srs, query: &Query, filter: &Filter) -> Result<Results> {
+    let mut results = Results {
+        hits: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for i in 0..srs.cards.len() as CardIndex {
+        if !filter.matches(srs, i) {
+            continue;
+        }
+
+        let found = crate::card(srs, i).and_then(|card| {
+            let front = query.find(&card.front)?;
+            let back = query.find(&card.back)?;
+            Ok((card, front, back))
+        });
+        let (card, front, back) = match found {
+            Ok(found) => found,
+            Err(e) => {
+                results.skipped.push((i, e.to_string()));
+                continue;
+            }
+        };
+
+        if front.is_some() || back.is_some() {
+            results.hits.push(Hit {
+                index: i,
+                card,
+                front,
+                back,
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, mode: Mode, text: &str) -> Option<Range<usize>> {
+        Query::new(query, mode).unwrap().find(text).unwrap()
+    }
+
+    #[test]
+    fn substring_ignores_case() {
+        assert_eq!(find("rust", Mode::Substring, "Trusty RUST"), Some(1..5));
+        assert_eq!(find("go", Mode::Substring, "rust"), None);
+        assert!(Query::new("", Mode::Substring).is_err());
+    }
+
+    #[test]
+    fn word_needs_boundaries() {
+        assert_eq!(find("rust", Mode::Word, "Trusty RUST"), Some(7..11));
+        assert_eq!(find("c++", Mode::Word, "use c++ here"), Some(4..7));
+        assert_eq!(find("cat", Mode::Word, "concat_cat"), None);
+    }
+
+    #[test]
+    fn word_agrees_with_regex_boundaries() {
+        for text in ["café é", "éé é", "日本 本"] {
+            let last = text.rsplit(' ').next().unwrap();
+            let regex = format!("\\b{last}\\b");
+
+            assert_eq!(
+                find(last, Mode::Word, text),
+                find(&regex, Mode::Regex, text),
+                "{text}",
+            );
+        }
+    }
+
+    #[test]
+    fn cards_which_are_too_complex_to_search_are_skipped() {
+        let srs = Srs {
+            cards: Box::new([
+                crate::encode_card(&"a".repeat(40), "").unwrap(),
+                crate::encode_card("c", "").unwrap(),
+            ]),
+            card_decks: Box::new([0, 0]),
+            card_tags: Box::new([Vec::new(), Vec::new()]),
+            ..Default::default()
+        };
+        let query = Query::new("(a|aa)*c", Mode::Regex).unwrap();
+
+        let results = search(&srs, &query, &Filter::default()).unwrap();
+
+        let skipped: Vec<CardIndex> = results.skipped.iter().map(|(i, _)| *i).collect();
+        assert_eq!(skipped, [0]);
+        assert_eq!(results.hits.len(), 1);
+        assert_eq!(results.hits[0].index, 1);
+    }
+
+    #[test]
+    fn cards_which_cant_be_decoded_are_skipped() {
+        let srs = Srs {
+            cards: Box::new([
+                b"no separator".to_vec(),
+                b"bad \xff utf-8\0back".to_vec(),
+                crate::encode_card("good", "").unwrap(),
+            ]),
+            card_decks: Box::new([0, 0, 0]),
+            card_tags: Box::new([Vec::new(), Vec::new(), Vec::new()]),
+            ..Default::default()
+        };
+        let query = Query::new("o", Mode::Substring).unwrap();
+
+        let results = search(&srs, &query, &Filter::default()).unwrap();
+
+        let skipped: Vec<CardIndex> = results.skipped.iter().map(|(i, _)| *i).collect();
+        assert_eq!(skipped, [0, 1]);
+        assert_eq!(results.hits.len(), 1);
+        assert_eq!(results.hits[0].index, 2);
+    }
+
+    #[test]
+    fn ranges_are_in_bytes() {
+        assert_eq!(find("語", Mode::Substring, "日本語"), Some(6..9));
+        assert_eq!(find("本.", Mode::Regex, "日本語!"), Some(3..9));
+    }
+}
@@ -0,0 +1,246 @@
+//! Free-form labels attached to cards, and expressions for filtering cards by them.
+//!
+//! Tags are lowercase, and can't contain whitespace or the characters used in expressions. An
+//! expression combines tags with `and`, `or`, `not` (or `&`, `|`, `!`) and parentheses, e.g.
+//! `rust and not (k8s | leech)`. `not` binds tightest, followed by `and`, then `or`.
+
+use crate::error::Result;
+use std::str::FromStr;
+
+// Tags and the number of tags on a card are stored as u8s.
+pub const MAX_TAG_LEN: usize = u8::MAX as usize;
+pub const MAX_TAG_COUNT: usize = u8::MAX as usize;
+
+const OPERATOR_CHARS: [char; 6] = ['(', ')', '!', '&', '|', ','];
+const KEYWORDS: [&str; 3] = ["and", "or", "not"];
+
+/// Parses a list of tags separated by whitespace or commas, as entered by a user. Tags are
+/// lowercased, and duplicates are removed.
+pub fn parse_list(s: &str) -> Result<Vec<String>> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in s.split(|c: char| c.is_whitespace() || c == ',') {
+        if tag.is_empty() {
+            continue;
+        }
+
+        let tag = tag.to_lowercase();
+        validate(&tag)?;
+
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    if tags.len() > MAX_TAG_COUNT {
+        return Err(format!("a card can't have more than {MAX_TAG_COUNT} tags").into());
+    }
+
+    Ok(tags)
+}
+
+fn validate(tag: &str) -> Result<()> {
+    if tag.len() > MAX_TAG_LEN {
+        return Err(format!("tag '{tag}' is longer than {MAX_TAG_LEN} bytes").into());
+    }
+
+    if let Some(c) = tag
+        .chars()
+        .find(|c| c.is_whitespace() || OPERATOR_CHARS.contains(c))
+    {
+        return Err(format!("tag '{tag}' can't contain {c:?}").into());
+    }
+
+    if tag.starts_with('-') {
+        return Err(format!("tag '{tag}' can't start with '-'").into());
+    }
+
+    if KEYWORDS.contains(&tag) {
+        return Err(format!("'{tag}' can't be used as a tag").into());
+    }
+
+    Ok(())
+}
+
+/// A boolean combination of tags, which a card's tags either match or don't.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// Matches cards with the tag.
+    Tag(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            Self::Tag(t) => tags.contains(t),
+            Self::Not(e) => !e.matches(tags),
+            Self::And(a, b) => a.matches(tags) && b.matches(tags),
+            Self::Or(a, b) => a.matches(tags) || b.matches(tags),
+        }
+    }
+
+    pub fn and(self, other: Self) -> Self {
+        Self::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn negate(self) -> Self {
+        Self::Not(Box::new(self))
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let tokens = tokenize(s);
+        let mut parser = Parser { tokens, pos: 0 };
+
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(format!("unexpected '{t}'")),
+        }
+    }
+}
+
+/// Splits an expression into tags, keywords, and operators.
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+
+    for c in s.chars() {
+        if c.is_whitespace() || OPERATOR_CHARS.contains(&c) {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            current.extend(c.to_lowercase());
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// A recursive descent parser over the tokens of an expression.
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    /// Consumes the next token if it's one of the given ones.
+    fn accept(&mut self, options: &[&str]) -> bool {
+        let is_match = self.peek().is_some_and(|t| options.contains(&t));
+        if is_match {
+            self.pos += 1;
+        }
+
+        is_match
+    }
+
+    fn or(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.accept(&["or", "|"]) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.accept(&["and", "&"]) {
+            expr = expr.and(self.unary()?);
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> std::result::Result<Expr, String> {
+        if self.accept(&["not", "!"]) {
+            return Ok(self.unary()?.negate());
+        }
+
+        if self.accept(&["("]) {
+            let expr = self.or()?;
+            if !self.accept(&[")"]) {
+                return Err("missing ')'".to_string());
+            }
+            return Ok(expr);
+        }
+
+        let Some(token) = self.peek() else {
+            return Err("expected a tag at the end of the expression".to_string());
+        };
+        if token.starts_with(OPERATOR_CHARS) || KEYWORDS.contains(&token) {
+            return Err(format!("expected a tag, but found '{token}'"));
+        }
+
+        let tag = token.to_string();
+        validate(&tag).map_err(|e| e.to_string())?;
+        self.pos += 1;
+
+        Ok(Expr::Tag(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(s: &str) -> Vec<String> {
+        parse_list(s).unwrap()
+    }
+
+    #[test]
+    fn list_is_normalised() {
+        assert_eq!(tags("Rust, k8s  rust\n"), ["rust", "k8s"]);
+        assert!(tags("").is_empty());
+        assert!(parse_list("a|b").is_err());
+        assert!(parse_list("not").is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        let expr: Expr = "a or b and not c".parse().unwrap();
+
+        assert!(expr.matches(&tags("a c")));
+        assert!(expr.matches(&tags("b")));
+        assert!(!expr.matches(&tags("b c")));
+        assert!(!expr.matches(&tags("")));
+    }
+
+    #[test]
+    fn symbols_and_parentheses() {
+        let expr: Expr = "(rust|go)&!leech".parse().unwrap();
+
+        assert!(expr.matches(&tags("go")));
+        assert!(!expr.matches(&tags("go leech")));
+        assert!(!expr.matches(&tags("k8s")));
+
+        assert_eq!(
+            "RUST".parse::<Expr>().unwrap(),
+            Expr::Tag("rust".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for s in ["", "a and", "(a", "a b", "a)", "and", "!"] {
+            assert!(s.parse::<Expr>().is_err(), "{s}");
+        }
+    }
+}
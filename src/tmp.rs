@@ -1,14 +1,33 @@
-use std::{env, path::PathBuf, time::SystemTime};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 /// Returns a path to a file in the OS's temp directory. The file isn't guaranteed to exist
 /// already.
 pub fn path() -> PathBuf {
-    let since_epoch = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("after unix epoch");
-
     let mut path = env::temp_dir();
-    path.push(format!("srs-cli_{}.txt", since_epoch.as_nanos()));
+    path.push(format!("srs-cli_{}.txt", nanos_since_epoch()));
 
     path
 }
+
+/// Returns a path to a hidden file in the same directory as the given path. Since it's on the same
+/// file system, the file can be renamed over the given path. The file isn't guaranteed to exist
+/// already.
+pub fn path_beside(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{name}.tmp-{}", nanos_since_epoch()))
+}
+
+fn nanos_since_epoch() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("after unix epoch")
+        .as_nanos()
+}
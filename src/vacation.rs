@@ -0,0 +1,95 @@
+//! Pushing due dates back for days when cards won't be reviewed, so that coming back doesn't mean
+//! a backlog of cards reviewed long after their intervals.
+
+use crate::error::Result;
+use crate::{Srs, update};
+use std::path::Path;
+
+/// Pushes the due date of every card back by the given number of days. Due dates past the last
+/// day that can be stored are capped at it, and lapsed cards are left as they are since they're
+/// due as soon as possible.
+pub fn shift(srs: Srs, path: &Path, days: u16) -> Result<()> {
+    update(srs, path, |srs| Ok(shifted(srs, days)))
+}
+
+/// Turns on vacation mode. No cards are due until it's turned off with [end].
+pub fn start(srs: Srs, path: &Path, now_in_epoch_days: u16) -> Result<()> {
+    update(srs, path, |srs| {
+        if let Some(day) = srs.vacation_start {
+            return Err(format!(
+                "vacation mode has been on since {}",
+                crate::date::format(day)
+            )
+            .into());
+        }
+
+        Ok(Srs {
+            vacation_start: Some(now_in_epoch_days),
+            ..srs
+        })
+    })
+}
+
+/// Turns off vacation mode, and pushes due dates back by the number of days it was on for, which
+/// is returned.
+pub fn end(srs: Srs, path: &Path, now_in_epoch_days: u16) -> Result<u16> {
+    let mut days = 0;
+
+    update(srs, path, |srs| {
+        let start = srs.vacation_start.ok_or("vacation mode isn't on")?;
+        days = now_in_epoch_days.saturating_sub(start);
+
+        Ok(Srs {
+            vacation_start: None,
+            ..shifted(srs, days)
+        })
+    })?;
+
+    Ok(days)
+}
+
+fn shifted(srs: Srs, days: u16) -> Srs {
+    let mut schedule = srs.schedule;
+    for sched in schedule.iter_mut().filter(|s| s.scheduled_for != 0) {
+        sched.scheduled_for = sched.scheduled_for.saturating_add(days);
+    }
+
+    Srs {
+        schedule,
+        shifted_days: srs.shifted_days.saturating_add(days),
+        ..srs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CardSchedule;
+
+    fn srs(scheduled_for: &[u16]) -> Srs {
+        Srs {
+            schedule: scheduled_for
+                .iter()
+                .map(|&scheduled_for| CardSchedule {
+                    most_recent_interval: 10,
+                    scheduled_for,
+                    memory: Default::default(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shifting_skips_lapsed_cards_and_saturates() {
+        let srs = shifted(srs(&[20_000, 0, u16::MAX - 3]), 14);
+
+        let scheduled_for: Vec<u16> = srs.schedule.iter().map(|s| s.scheduled_for).collect();
+        assert_eq!(scheduled_for, [20_014, 0, u16::MAX]);
+        assert_eq!(srs.shifted_days, 14);
+
+        let srs = shifted(srs, 7);
+        assert_eq!(srs.schedule[0].scheduled_for, 20_021);
+        assert_eq!(srs.shifted_days, 21);
+    }
+}